@@ -0,0 +1,469 @@
+use std::cmp::Reverse;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+use crate::manifest::DownloadStep;
+
+/// Default cache size limit, in bytes.
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1_073_741_824;
+/// Age after which an index lock is assumed to be left by a crashed process.
+const STALE_LOCK: Duration = Duration::from_secs(30);
+/// Pause between attempts to take the index lock.
+const LOCK_RETRY: Duration = Duration::from_millis(50);
+
+/// One cached file, stored as `objects/<key>` under the cache directory.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// `sha256-<hex>` or `sha512-<hex>` for checksummed downloads, otherwise
+    /// `etag-<hex>` derived from the URL and its ETag.
+    pub key: String,
+    pub size: u64,
+    /// SHA-256 of the stored file, checked by `cache verify`.
+    pub sha256: String,
+    /// The URL the file was downloaded from.
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    pub created: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct CacheIndex {
+    #[serde(default)]
+    entries: Vec<CacheEntry>,
+}
+
+/// Outcome of [`DownloadCache::verify`].
+#[derive(Debug, Serialize, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    /// Entries whose file was missing or no longer matched; they are removed.
+    pub removed: Vec<CacheEntry>,
+}
+
+/// Content-addressed store of downloaded files shared by every install,
+/// evicting least recently used entries beyond `max_size` bytes.
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    root: PathBuf,
+    max_size: u64,
+}
+
+impl DownloadCache {
+    pub fn new(root: PathBuf, max_size: u64) -> Self {
+        DownloadCache { root, max_size }
+    }
+
+    /// `enzyme-installer/cache` under the platform data directory, next to
+    /// the install state.
+    pub fn default_dir() -> anyhow::Result<PathBuf> {
+        Ok(dirs::data_dir()
+            .ok_or_else(|| anyhow!("could not determine platform data directory"))?
+            .join("enzyme-installer")
+            .join("cache"))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Entries, most recently used first.
+    pub fn entries(&self) -> anyhow::Result<Vec<CacheEntry>> {
+        let mut entries = self.load_index()?.entries;
+        entries.sort_by_key(|entry| Reverse(entry.last_used));
+        Ok(entries)
+    }
+
+    /// Whether any entry was downloaded from `url`.
+    pub fn has_url(&self, url: &str) -> bool {
+        self.load_index()
+            .is_ok_and(|index| index.entries.iter().any(|entry| entry.url == url))
+    }
+
+    /// Look up `key`, marking it as used. Returns the entry and the path of
+    /// its file; entries whose file has gone missing are dropped.
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<(CacheEntry, PathBuf)>> {
+        let path = self.object_path(key);
+        self.update(|index| {
+            let pos = index.entries.iter().position(|entry| entry.key == key)?;
+            if fs::metadata(&path).map(|meta| meta.len()).ok() != Some(index.entries[pos].size) {
+                let _ = fs::remove_file(&path);
+                index.entries.remove(pos);
+                return None;
+            }
+            index.entries[pos].last_used = Utc::now();
+            Some((index.entries[pos].clone(), path))
+        })
+    }
+
+    /// Copy `file` into the cache under `key`, then evict least recently used
+    /// entries until the cache fits its size limit. Files larger than the
+    /// limit are not cached.
+    pub fn insert(
+        &self,
+        key: &str,
+        file: &Path,
+        url: &str,
+        etag: Option<String>,
+    ) -> anyhow::Result<()> {
+        let len = fs::metadata(file)
+            .with_context(|| format!("reading metadata of {}", file.display()))?
+            .len();
+        if len > self.max_size {
+            return Ok(());
+        }
+
+        let objects = self.root.join("objects");
+        fs::create_dir_all(&objects)
+            .with_context(|| format!("creating cache directory {}", objects.display()))?;
+        let mut tmp = NamedTempFile::new_in(&objects)
+            .with_context(|| format!("creating temp file in {}", objects.display()))?;
+        let (size, sha256) = copy_hashing(file, tmp.as_file_mut())?;
+
+        let path = self.object_path(key);
+        self.update(|index| {
+            tmp.persist(&path)
+                .with_context(|| format!("committing cache object {}", path.display()))?;
+            index.entries.retain(|entry| entry.key != key);
+            let now = Utc::now();
+            index.entries.push(CacheEntry {
+                key: key.to_string(),
+                size,
+                sha256,
+                url: url.to_string(),
+                etag,
+                created: now,
+                last_used: now,
+            });
+            self.evict(index, self.max_size);
+            Ok(())
+        })?
+    }
+
+    pub fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.update(|index| {
+            index.entries.retain(|entry| entry.key != key);
+            let _ = fs::remove_file(self.object_path(key));
+        })
+    }
+
+    /// Re-hash every entry, removing those whose file is missing or changed.
+    pub fn verify(&self) -> anyhow::Result<VerifyReport> {
+        // Hash without holding the lock; it can take a while.
+        let entries = self.load_index()?.entries;
+        let checked = entries.len();
+        let corrupt: Vec<CacheEntry> = entries
+            .into_iter()
+            .filter(|entry| {
+                !hash_file(&self.object_path(&entry.key))
+                    .is_ok_and(|(size, sha256)| size == entry.size && sha256 == entry.sha256)
+            })
+            .collect();
+        self.update(|index| {
+            index
+                .entries
+                .retain(|entry| !corrupt.iter().any(|bad| bad.key == entry.key));
+            for entry in &corrupt {
+                let _ = fs::remove_file(self.object_path(&entry.key));
+            }
+        })?;
+        Ok(VerifyReport {
+            checked,
+            removed: corrupt,
+        })
+    }
+
+    /// Evict least recently used entries until the cache holds at most
+    /// `max_size` bytes (everything when `max_size` is 0), returning them.
+    pub fn clean(&self, max_size: u64) -> anyhow::Result<Vec<CacheEntry>> {
+        self.update(|index| self.evict(index, max_size))
+    }
+
+    /// Read, change and write back the index while holding the index lock,
+    /// so concurrent installs do not lose each other's entries.
+    fn update<T>(&self, change: impl FnOnce(&mut CacheIndex) -> T) -> anyhow::Result<T> {
+        let _lock = self.lock()?;
+        let mut index = self.load_index()?;
+        let result = change(&mut index);
+        self.save_index(&index)?;
+        Ok(result)
+    }
+
+    /// Take `index.lock`, waiting for other processes to release it. A lock
+    /// older than [`STALE_LOCK`] is taken over.
+    fn lock(&self) -> anyhow::Result<IndexLock> {
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("creating cache directory {}", self.root.display()))?;
+        let path = self.root.join("index.lock");
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(IndexLock(path)),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    let stale = fs::metadata(&path)
+                        .and_then(|meta| meta.modified())
+                        .is_ok_and(|modified| {
+                            SystemTime::now()
+                                .duration_since(modified)
+                                .is_ok_and(|age| age > STALE_LOCK)
+                        });
+                    if stale {
+                        let _ = fs::remove_file(&path);
+                    } else {
+                        thread::sleep(LOCK_RETRY);
+                    }
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("taking cache lock {}", path.display()));
+                }
+            }
+        }
+    }
+
+    fn evict(&self, index: &mut CacheIndex, max_size: u64) -> Vec<CacheEntry> {
+        index.entries.sort_by_key(|entry| Reverse(entry.last_used));
+        let mut total = 0;
+        let mut removed = Vec::new();
+        index.entries.retain(|entry| {
+            total += entry.size;
+            let keep = max_size > 0 && total <= max_size;
+            if !keep {
+                let _ = fs::remove_file(self.object_path(&entry.key));
+                removed.push(entry.clone());
+            }
+            keep
+        });
+        removed
+    }
+
+    fn object_path(&self, key: &str) -> PathBuf {
+        self.root.join("objects").join(key)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.root.join("index.json")
+    }
+
+    fn load_index(&self) -> anyhow::Result<CacheIndex> {
+        let path = self.index_path();
+        if !path.exists() {
+            return Ok(CacheIndex::default());
+        }
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("reading cache index at {}", path.display()))?;
+        serde_json::from_str(&data)
+            .with_context(|| format!("parsing cache index at {}", path.display()))
+    }
+
+    fn save_index(&self, index: &CacheIndex) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("creating cache directory {}", self.root.display()))?;
+        let path = self.index_path();
+        let mut tmp = NamedTempFile::new_in(&self.root)
+            .with_context(|| format!("creating temp file in {}", self.root.display()))?;
+        let data = serde_json::to_string_pretty(index)?;
+        tmp.write_all(data.as_bytes())
+            .with_context(|| format!("writing temp cache index {}", tmp.path().display()))?;
+        tmp.persist(&path)
+            .with_context(|| format!("committing cache index to {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Removes the index lock file when dropped.
+struct IndexLock(PathBuf);
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Cache key for a step that declares a digest, preferring SHA-256.
+pub fn checksum_key(step: &DownloadStep) -> Option<String> {
+    step.sha256
+        .as_ref()
+        .map(|digest| format!("sha256-{}", digest.to_ascii_lowercase()))
+        .or_else(|| {
+            step.sha512
+                .as_ref()
+                .map(|digest| format!("sha512-{}", digest.to_ascii_lowercase()))
+        })
+}
+
+/// Cache key for a download without a declared digest.
+pub fn etag_key(url: &str, etag: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url.as_bytes());
+    hasher.update([0]);
+    hasher.update(etag.as_bytes());
+    format!("etag-{:x}", hasher.finalize())
+}
+
+fn hash_file(path: &Path) -> anyhow::Result<(u64, String)> {
+    let mut file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    hash_into(&mut file, &mut std::io::sink(), path)
+}
+
+fn copy_hashing(from: &Path, to: &mut File) -> anyhow::Result<(u64, String)> {
+    let mut source = File::open(from).with_context(|| format!("opening {}", from.display()))?;
+    hash_into(&mut source, to, from)
+}
+
+fn hash_into(
+    source: &mut impl Read,
+    dest: &mut impl Write,
+    path: &Path,
+) -> anyhow::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = source
+            .read(&mut buf)
+            .with_context(|| format!("reading {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        dest.write_all(&buf[..read])?;
+        size += read as u64;
+    }
+    dest.flush()?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{DownloadCache, checksum_key, etag_key};
+    use crate::manifest::DownloadStep;
+
+    fn cache(root: &Path, max_size: u64) -> DownloadCache {
+        DownloadCache::new(root.join("cache"), max_size)
+    }
+
+    fn insert(cache: &DownloadCache, dir: &Path, key: &str, contents: &str) {
+        let file = dir.join(format!("{key}.src"));
+        std::fs::write(&file, contents).unwrap();
+        cache
+            .insert(key, &file, &format!("https://cdn.example.com/{key}"), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn stores_and_returns_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(dir.path(), 1024);
+        insert(&cache, dir.path(), "sha256-abc", "runtime");
+
+        let (entry, path) = cache.get("sha256-abc").unwrap().expect("entry is cached");
+        assert_eq!(entry.size, 7);
+        assert_eq!(entry.url, "https://cdn.example.com/sha256-abc");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "runtime");
+        assert!(cache.has_url("https://cdn.example.com/sha256-abc"));
+        assert!(cache.get("sha256-def").unwrap().is_none());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries_beyond_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(dir.path(), 10);
+        insert(&cache, dir.path(), "a", "aaaa");
+        insert(&cache, dir.path(), "b", "bbbb");
+        cache.get("a").unwrap();
+        insert(&cache, dir.path(), "c", "cccc");
+
+        let keys: Vec<String> = cache
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+        assert_eq!(keys, vec!["c", "a"]);
+        assert!(!cache.root().join("objects/b").exists());
+
+        let removed = cache.clean(0).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn skips_files_larger_than_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(dir.path(), 4);
+        insert(&cache, dir.path(), "big", "too large");
+
+        assert!(cache.entries().unwrap().is_empty());
+        assert!(!cache.root().join("objects").exists());
+    }
+
+    #[test]
+    fn concurrent_inserts_keep_every_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(dir.path(), 1024);
+        std::thread::scope(|scope| {
+            for n in 0..8 {
+                let (cache, dir) = (&cache, dir.path());
+                scope.spawn(move || insert(cache, dir, &format!("key-{n}"), "contents"));
+            }
+        });
+
+        assert_eq!(cache.entries().unwrap().len(), 8);
+        assert!(!cache.root().join("index.lock").exists());
+    }
+
+    #[test]
+    fn verify_removes_changed_and_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(dir.path(), 1024);
+        insert(&cache, dir.path(), "good", "same");
+        insert(&cache, dir.path(), "changed", "before");
+        insert(&cache, dir.path(), "missing", "gone");
+        std::fs::write(cache.root().join("objects/changed"), "after!").unwrap();
+        std::fs::remove_file(cache.root().join("objects/missing")).unwrap();
+
+        let report = cache.verify().unwrap();
+        assert_eq!(report.checked, 3);
+        let mut removed: Vec<&str> = report.removed.iter().map(|e| e.key.as_str()).collect();
+        removed.sort();
+        assert_eq!(removed, vec!["changed", "missing"]);
+        assert_eq!(cache.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn keys_prefer_checksums() {
+        let mut step = DownloadStep {
+            url: "https://cdn.example.com/app.zip".into(),
+            urls: vec![],
+            dest: "app.zip".into(),
+            sha256: None,
+            sha512: Some("AB".repeat(64)),
+            size: None,
+        };
+        assert_eq!(
+            checksum_key(&step),
+            Some(format!("sha512-{}", "ab".repeat(64)))
+        );
+        step.sha256 = Some("cd".repeat(32));
+        assert_eq!(
+            checksum_key(&step),
+            Some(format!("sha256-{}", "cd".repeat(32)))
+        );
+
+        let key = etag_key("https://cdn.example.com/app.zip", "\"v1\"");
+        assert!(key.starts_with("etag-"));
+        assert_ne!(key, etag_key("https://cdn.example.com/app.zip", "\"v2\""));
+    }
+}
@@ -1,14 +1,38 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use chrono::Utc;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use indicatif::HumanBytes;
 use serde::Serialize;
 
-use crate::env_detect::detect_environment;
-use crate::executor::{ExecutionError, ExecutionResult, execute_plan};
-use crate::manifest::load_manifest;
-use crate::planner::{InstallPlan, PlannerError, plan_install};
+use crate::cache::{CacheEntry, DEFAULT_MAX_SIZE, DownloadCache};
+use crate::download::DownloadOptions;
+use crate::env_detect::{
+    Environment, detect_environment, disk_free_gb_at, load_environment, probe_commands,
+    save_environment,
+};
+use crate::executor::{
+    ExecutionError, ExecutionOptions, ExecutionResult, execute_plan_with_options,
+};
+use crate::manifest::{
+    Manifest, ManifestFormat, ManifestValidationErrors, ValidationIssue, load_manifest_with_format,
+    manifest_schema, manifest_warnings,
+};
+use crate::matrix::{
+    AssertionFailure, MatrixRow, ReportFormat, check_assertions, load_assertions, load_profiles,
+    plan_matrix, render_report,
+};
+use crate::mirrors::{MirrorConfig, default_mirror_config_path, load_mirror_config};
+use crate::planner::{
+    CheckOutcome, Explanation, InstallPlan, PlanOptions, PlannerError, RequirementCheck,
+    explain_modes, install_target, plan_install_with_options,
+};
+use crate::progress::cli_reporter;
 use crate::state::{InstallRecord, InstallStatus, add_install_record, load_state};
+use crate::vars::parse_assignment;
+
+const MIB: u64 = 1 << 20;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -28,20 +52,215 @@ pub struct Cli {
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// Detect the current environment
-    Detect,
+    Detect {
+        /// Also write the environment to this file, for use with --env
+        #[arg(long, value_name = "FILE")]
+        save: Option<PathBuf>,
+        /// Record the version of this command (repeatable)
+        #[arg(long = "probe", value_name = "COMMAND")]
+        probe: Vec<String>,
+    },
     /// Build an installation plan from a manifest without executing it
     Plan {
-        /// Path to the manifest file
-        manifest_path: PathBuf,
+        #[command(flatten)]
+        manifest: ManifestArgs,
+        #[command(flatten)]
+        plan: PlanArgs,
+        #[command(flatten)]
+        env: EnvArgs,
     },
     /// Build and execute an installation plan
     Install {
-        /// Path to the manifest file
-        manifest_path: PathBuf,
+        #[command(flatten)]
+        manifest: ManifestArgs,
+        #[command(flatten)]
+        plan: PlanArgs,
+        #[command(flatten)]
+        download: DownloadArgs,
     },
     /// List previous installs recorded on this machine
     #[command(name = "list-installed")]
     ListInstalled,
+    /// Print the JSON Schema describing the manifest format
+    Schema,
+    /// Show how every mode's requirements compare with this machine
+    Explain {
+        #[command(flatten)]
+        manifest: ManifestArgs,
+        #[command(flatten)]
+        env: EnvArgs,
+    },
+    /// Plan a manifest against every saved environment profile in a directory
+    #[command(name = "plan-matrix")]
+    PlanMatrix {
+        #[command(flatten)]
+        manifest: ManifestArgs,
+        /// Directory of environment profiles saved with `detect --save`
+        #[arg(long, value_name = "DIR")]
+        envs: PathBuf,
+        /// Report format (markdown, csv, json)
+        #[arg(long, default_value_t = ReportFormat::Markdown)]
+        report: ReportFormat,
+        /// JSON file mapping profile names to the expected mode (null for none);
+        /// any mismatch exits with status 4
+        #[arg(long, value_name = "FILE")]
+        expect: Option<PathBuf>,
+    },
+    /// Check a manifest and report every validation problem
+    Validate {
+        #[command(flatten)]
+        manifest: ManifestArgs,
+    },
+    /// Inspect and maintain the download cache shared by all installs
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheAction {
+    /// List cached downloads, most recently used first
+    List,
+    /// Re-hash every cached download and remove any that changed on disk
+    Verify,
+    /// Remove cached downloads, least recently used first
+    Clean {
+        /// Keep up to this many MiB of the most recently used downloads
+        /// [default: remove everything]
+        #[arg(long, value_name = "MIB")]
+        keep: Option<u64>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct ManifestArgs {
+    /// Path to the manifest file
+    manifest_path: PathBuf,
+    /// Manifest format (json, yaml, toml); inferred from the extension by default
+    #[arg(long)]
+    format: Option<ManifestFormat>,
+}
+
+impl ManifestArgs {
+    fn load(&self) -> anyhow::Result<Manifest> {
+        load_manifest_with_format(&self.manifest_path, self.format)
+    }
+}
+
+#[derive(Debug, Args)]
+struct EnvArgs {
+    /// Use an environment saved with `detect --save` instead of detecting this machine
+    #[arg(long = "env", value_name = "FILE")]
+    env_path: Option<PathBuf>,
+}
+
+impl EnvArgs {
+    /// Load the saved profile, or detect this machine, probe the commands
+    /// `manifest` requires and measure free disk where it installs. Saved
+    /// profiles are used as-is.
+    fn resolve(&self, manifest: &Manifest, options: &PlanOptions) -> anyhow::Result<Environment> {
+        match &self.env_path {
+            Some(path) => load_environment(path),
+            None => detect_for_manifest(manifest, options),
+        }
+    }
+}
+
+fn detect_for_manifest(manifest: &Manifest, options: &PlanOptions) -> anyhow::Result<Environment> {
+    let mut env = detect_environment()?;
+    probe_commands(&mut env, manifest.required_commands());
+    env.disk_free_gb_by_mode = manifest
+        .modes
+        .keys()
+        .filter_map(|name| {
+            let free = disk_free_gb_at(&install_target(manifest, name, &env, options))?;
+            Some((name.clone(), free))
+        })
+        .collect();
+    Ok(env)
+}
+
+#[derive(Debug, Args)]
+struct PlanArgs {
+    /// Override a manifest variable (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_assignment)]
+    vars: Vec<(String, String)>,
+    /// Use this mode instead of picking the best compatible one
+    #[arg(long)]
+    mode: Option<String>,
+    /// With --mode, proceed even if the mode's requirements are not met
+    #[arg(long, requires = "mode")]
+    force: bool,
+}
+
+impl PlanArgs {
+    fn options(&self) -> PlanOptions {
+        PlanOptions {
+            vars: self.vars.iter().cloned().collect(),
+            mode: self.mode.clone(),
+            force: self.force,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct DownloadArgs {
+    /// Retry a failed download this many times, with exponential backoff [default: 3]
+    #[arg(long, value_name = "N")]
+    retries: Option<u32>,
+    /// Seconds to wait for a download connection [default: 15]
+    #[arg(long, value_name = "SECS")]
+    connect_timeout: Option<u64>,
+    /// Seconds to wait for download data before retrying [default: 60]
+    #[arg(long, value_name = "SECS")]
+    read_timeout: Option<u64>,
+    /// JSON file mapping URL prefixes to mirrors [default: mirrors.json in the
+    /// enzyme-installer config directory, when present]
+    #[arg(long, value_name = "FILE")]
+    mirrors: Option<PathBuf>,
+    /// Always download, without reading or filling the download cache
+    #[arg(long)]
+    no_cache: bool,
+    /// Evict least recently used downloads once the cache exceeds this many MiB
+    /// [default: 10240]
+    #[arg(long, value_name = "MIB")]
+    cache_max_size: Option<u64>,
+}
+
+impl DownloadArgs {
+    fn options(&self, json: bool) -> anyhow::Result<ExecutionOptions> {
+        let mirrors_path = self
+            .mirrors
+            .clone()
+            .or_else(|| default_mirror_config_path().filter(|path| path.exists()));
+        let mut download = DownloadOptions {
+            progress: Some(cli_reporter(json)),
+            mirrors: match mirrors_path {
+                Some(path) => load_mirror_config(&path)?,
+                None => MirrorConfig::default(),
+            },
+            cache: if self.no_cache {
+                None
+            } else {
+                let max_size = self
+                    .cache_max_size
+                    .map_or(DEFAULT_MAX_SIZE, |mib| mib.saturating_mul(MIB));
+                Some(DownloadCache::new(DownloadCache::default_dir()?, max_size))
+            },
+            ..Default::default()
+        };
+        if let Some(retries) = self.retries {
+            download.retries = retries;
+        }
+        if let Some(secs) = self.connect_timeout {
+            download.connect_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.read_timeout {
+            download.read_timeout = Duration::from_secs(secs);
+        }
+        Ok(ExecutionOptions { download })
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -67,7 +286,9 @@ struct PlanErrorResponse {
     message: String,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     details: Vec<String>,
-    environment: Option<crate::env_detect::Environment>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    validation_errors: Vec<ValidationIssue>,
+    environment: Option<Environment>,
 }
 
 #[derive(Debug, Serialize)]
@@ -88,6 +309,65 @@ struct InstallErrorResponse {
     failed_step_index: Option<usize>,
 }
 
+#[derive(Debug, Serialize)]
+struct ValidateResponse {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest: Option<ManifestSummary>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<ValidationIssue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PlanErrorResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExplainResponse {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<Environment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PlanErrorResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct MatrixResponse {
+    ok: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rows: Vec<MatrixRow>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    assertion_failures: Vec<AssertionFailure>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PlanErrorResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestSummary {
+    name: String,
+    version: String,
+    modes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SchemaResponse {
+    ok: bool,
+    schema: schemars::schema::RootSchema,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheResponse {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries: Option<Vec<CacheEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checked: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    removed: Option<Vec<CacheEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 struct ListResponse {
     ok: bool,
@@ -99,10 +379,28 @@ pub fn run() -> i32 {
     let json = cli.json;
 
     let exit_code = match cli.command {
-        Commands::Detect => handle_detect(json),
-        Commands::Plan { manifest_path } => handle_plan(json, manifest_path),
-        Commands::Install { manifest_path } => handle_install(json, manifest_path),
+        Commands::Detect { save, probe } => handle_detect(json, save.as_deref(), &probe),
+        Commands::Plan {
+            manifest,
+            plan,
+            env,
+        } => handle_plan(json, &manifest, &plan, &env),
+        Commands::Install {
+            manifest,
+            plan,
+            download,
+        } => handle_install(json, &manifest, &plan, &download),
         Commands::ListInstalled => handle_list_installed(json),
+        Commands::Schema => handle_schema(json),
+        Commands::Validate { manifest } => handle_validate(json, &manifest),
+        Commands::PlanMatrix {
+            manifest,
+            envs,
+            report,
+            expect,
+        } => handle_plan_matrix(json, &manifest, &envs, report, expect.as_deref()),
+        Commands::Explain { manifest, env } => handle_explain(json, &manifest, &env),
+        Commands::Cache { action } => handle_cache(json, &action),
     };
 
     if exit_code != 0 {
@@ -112,8 +410,15 @@ pub fn run() -> i32 {
     0
 }
 
-fn handle_detect(json: bool) -> i32 {
-    match detect_environment() {
+fn handle_detect(json: bool, save: Option<&Path>, probe: &[String]) -> i32 {
+    let detected = detect_environment().and_then(|mut env| {
+        probe_commands(&mut env, probe.iter().map(String::as_str));
+        if let Some(path) = save {
+            save_environment(&env, path)?;
+        }
+        Ok(env)
+    });
+    match detected {
         Ok(env) => {
             if json {
                 print_json(&DetectResponse {
@@ -131,38 +436,40 @@ fn handle_detect(json: bool) -> i32 {
                 print_json(&DetectResponse::<()> {
                     ok: false,
                     environment: None,
-                    error: Some(err.to_string()),
+                    error: Some(format!("{err:#}")),
                 });
             } else {
-                eprintln!("{err}");
+                eprintln!("{err:#}");
             }
             1
         }
     }
 }
 
-fn handle_plan(json: bool, manifest_path: PathBuf) -> i32 {
-    let manifest = match load_manifest(&manifest_path) {
+fn handle_plan(
+    json: bool,
+    manifest_args: &ManifestArgs,
+    plan_args: &PlanArgs,
+    env_args: &EnvArgs,
+) -> i32 {
+    let manifest = match manifest_args.load() {
         Ok(m) => m,
         Err(err) => {
+            let error = manifest_error_response(err);
             if json {
                 print_json(&PlanResponse {
                     ok: false,
                     plan: None,
-                    error: Some(PlanErrorResponse {
-                        message: err.to_string(),
-                        details: Vec::new(),
-                        environment: None,
-                    }),
+                    error: Some(error),
                 });
             } else {
-                eprintln!("{err}");
+                print_manifest_error(&error);
             }
             return 1;
         }
     };
 
-    let env = match detect_environment() {
+    let env = match env_args.resolve(&manifest, &plan_args.options()) {
         Ok(env) => env,
         Err(err) => {
             if json {
@@ -170,19 +477,20 @@ fn handle_plan(json: bool, manifest_path: PathBuf) -> i32 {
                     ok: false,
                     plan: None,
                     error: Some(PlanErrorResponse {
-                        message: err.to_string(),
+                        message: format!("{err:#}"),
                         details: Vec::new(),
+                        validation_errors: Vec::new(),
                         environment: None,
                     }),
                 });
             } else {
-                eprintln!("{err}");
+                eprintln!("{err:#}");
             }
             return 1;
         }
     };
 
-    match plan_install(&manifest, &env) {
+    match plan_install_with_options(&manifest, &env, &plan_args.options()) {
         Ok(plan) => {
             if json {
                 print_json(&PlanResponse {
@@ -198,27 +506,23 @@ fn handle_plan(json: bool, manifest_path: PathBuf) -> i32 {
                     plan.chosen_mode,
                     plan.steps.len()
                 );
+                println!("Mode selection: {}", plan.selection_reason);
+                print_plan_warnings(&plan);
                 println!("{}", serde_json::to_string_pretty(&plan).unwrap());
             }
             0
         }
-        Err(PlannerError::NoCompatibleMode {
-            environment,
-            reasons,
-        }) => {
+        Err(err) => {
+            let error = planner_error_response(err);
             if json {
                 print_json(&PlanResponse {
                     ok: false,
                     plan: None,
-                    error: Some(PlanErrorResponse {
-                        message: "No compatible modes for this environment".to_string(),
-                        details: reasons,
-                        environment: Some(environment),
-                    }),
+                    error: Some(error),
                 });
             } else {
-                eprintln!("No compatible modes found:");
-                for reason in reasons {
+                eprintln!("{}:", error.message);
+                for reason in &error.details {
                     eprintln!("- {reason}");
                 }
             }
@@ -227,16 +531,29 @@ fn handle_plan(json: bool, manifest_path: PathBuf) -> i32 {
     }
 }
 
-fn handle_install(json: bool, manifest_path: PathBuf) -> i32 {
-    let manifest = match load_manifest(&manifest_path) {
+fn handle_install(
+    json: bool,
+    manifest_args: &ManifestArgs,
+    plan_args: &PlanArgs,
+    download_args: &DownloadArgs,
+) -> i32 {
+    let manifest = match manifest_args.load() {
         Ok(m) => m,
         Err(err) => {
-            emit_install_error(json, None, &err.to_string(), None);
+            emit_install_error(json, None, &format!("{err:#}"), None);
+            return 1;
+        }
+    };
+
+    let options = match download_args.options(json) {
+        Ok(options) => options,
+        Err(err) => {
+            emit_install_error(json, None, &format!("{err:#}"), None);
             return 1;
         }
     };
 
-    let env = match detect_environment() {
+    let env = match detect_for_manifest(&manifest, &plan_args.options()) {
         Ok(env) => env,
         Err(err) => {
             emit_install_error(json, None, &err.to_string(), None);
@@ -244,30 +561,22 @@ fn handle_install(json: bool, manifest_path: PathBuf) -> i32 {
         }
     };
 
-    let plan = match plan_install(&manifest, &env) {
+    let plan = match plan_install_with_options(&manifest, &env, &plan_args.options()) {
         Ok(plan) => plan,
-        Err(PlannerError::NoCompatibleMode {
-            environment,
-            reasons,
-        }) => {
-            let message = "No compatible modes for this environment".to_string();
-            let detail = PlanErrorResponse {
-                message: message.clone(),
-                details: reasons.clone(),
-                environment: Some(environment),
-            };
+        Err(err) => {
+            let detail = planner_error_response(err);
             if json {
                 print_json(&InstallResponse {
                     ok: false,
                     plan: None,
                     result: None,
                     error: Some(InstallErrorResponse {
-                        message: format!("{message}: {}", reasons.join("; ")),
+                        message: format!("{}: {}", detail.message, detail.details.join("; ")),
                         failed_step_index: None,
                     }),
                 });
             } else {
-                eprintln!("No compatible modes found for install.");
+                eprintln!("Cannot install: {}", detail.message);
                 eprintln!("{}", serde_json::to_string_pretty(&detail).unwrap());
             }
             return 2;
@@ -282,9 +591,11 @@ fn handle_install(json: bool, manifest_path: PathBuf) -> i32 {
             plan.chosen_mode,
             plan.steps.len()
         );
+        println!("Mode selection: {}", plan.selection_reason);
+        print_plan_warnings(&plan);
     }
 
-    match execute_plan(&plan) {
+    match execute_plan_with_options(&plan, &options) {
         Ok(result) => {
             let record = InstallRecord {
                 app_name: manifest.name.clone(),
@@ -294,6 +605,7 @@ fn handle_install(json: bool, manifest_path: PathBuf) -> i32 {
                 cpu_arch: env.cpu_arch.clone(),
                 timestamp: Utc::now(),
                 status: InstallStatus::Success,
+                downloads: result.downloads.clone(),
             };
             let _ = add_install_record(record);
 
@@ -310,9 +622,11 @@ fn handle_install(json: bool, manifest_path: PathBuf) -> i32 {
             0
         }
         Err(err) => {
-            let failed_index = match &err {
-                ExecutionError::StepFailed { index, .. } => Some(*index),
-                _ => None,
+            let (failed_index, downloads) = match &err {
+                ExecutionError::StepFailed {
+                    index, downloads, ..
+                } => (Some(*index), downloads.clone()),
+                _ => (None, Vec::new()),
             };
             let record = InstallRecord {
                 app_name: manifest.name.clone(),
@@ -322,6 +636,7 @@ fn handle_install(json: bool, manifest_path: PathBuf) -> i32 {
                 cpu_arch: env.cpu_arch.clone(),
                 timestamp: Utc::now(),
                 status: InstallStatus::Failed,
+                downloads,
             };
             let _ = add_install_record(record);
 
@@ -355,6 +670,14 @@ fn handle_list_installed(json: bool) -> i32 {
                             record.status,
                             record.timestamp
                         );
+                        for download in &record.downloads {
+                            println!(
+                                "    {} from {}{}",
+                                download.dest.display(),
+                                download.url,
+                                if download.cached { " (cached)" } else { "" }
+                            );
+                        }
                     }
                 }
             }
@@ -368,6 +691,7 @@ fn handle_list_installed(json: bool) -> i32 {
                     error: Some(PlanErrorResponse {
                         message: err.to_string(),
                         details: Vec::new(),
+                        validation_errors: Vec::new(),
                         environment: None,
                     }),
                 });
@@ -379,6 +703,402 @@ fn handle_list_installed(json: bool) -> i32 {
     }
 }
 
+fn handle_cache(json: bool, action: &CacheAction) -> i32 {
+    let response = DownloadCache::default_dir().and_then(|dir| {
+        let cache = DownloadCache::new(dir, DEFAULT_MAX_SIZE);
+        let mut response = CacheResponse {
+            ok: true,
+            entries: None,
+            checked: None,
+            removed: None,
+            error: None,
+        };
+        match action {
+            CacheAction::List => response.entries = Some(cache.entries()?),
+            CacheAction::Verify => {
+                let report = cache.verify()?;
+                response.checked = Some(report.checked);
+                response.removed = Some(report.removed);
+            }
+            CacheAction::Clean { keep } => {
+                response.removed = Some(cache.clean(keep.unwrap_or(0).saturating_mul(MIB))?);
+            }
+        }
+        Ok((cache, response))
+    });
+
+    let (cache, response) = match response {
+        Ok(done) => done,
+        Err(err) => {
+            if json {
+                print_json(&CacheResponse {
+                    ok: false,
+                    entries: None,
+                    checked: None,
+                    removed: None,
+                    error: Some(format!("{err:#}")),
+                });
+            } else {
+                eprintln!("{err:#}");
+            }
+            return 1;
+        }
+    };
+    if json {
+        print_json(&response);
+        return 0;
+    }
+
+    if let Some(entries) = &response.entries {
+        if entries.is_empty() {
+            println!("Download cache at {} is empty.", cache.root().display());
+        } else {
+            let total: u64 = entries.iter().map(|entry| entry.size).sum();
+            println!(
+                "Download cache at {} ({} entries, {}):",
+                cache.root().display(),
+                entries.len(),
+                HumanBytes(total)
+            );
+            for entry in entries {
+                println!(
+                    "- {} {} last used {}",
+                    entry.key,
+                    HumanBytes(entry.size),
+                    entry.last_used
+                );
+                println!("    from {}", entry.url);
+            }
+        }
+    }
+    if let Some(checked) = response.checked {
+        println!("Checked {checked} cached downloads.");
+    }
+    if let Some(removed) = &response.removed {
+        for entry in removed {
+            println!("Removed {} ({})", entry.key, HumanBytes(entry.size));
+        }
+        println!(
+            "Removed {} cached downloads, freeing {}.",
+            removed.len(),
+            HumanBytes(removed.iter().map(|entry| entry.size).sum())
+        );
+    }
+    0
+}
+
+fn handle_validate(json: bool, manifest_args: &ManifestArgs) -> i32 {
+    match manifest_args.load() {
+        Ok(manifest) => {
+            let warnings = manifest_warnings(&manifest);
+            let summary = ManifestSummary {
+                name: manifest.name,
+                version: manifest.version,
+                modes: manifest.modes.into_keys().collect(),
+            };
+            if json {
+                print_json(&ValidateResponse {
+                    ok: true,
+                    manifest: Some(summary),
+                    warnings,
+                    error: None,
+                });
+            } else {
+                for warning in &warnings {
+                    eprintln!("Warning: {warning}");
+                }
+                println!(
+                    "Manifest {} {} is valid ({} modes: {})",
+                    summary.name,
+                    summary.version,
+                    summary.modes.len(),
+                    summary.modes.join(", ")
+                );
+            }
+            0
+        }
+        Err(err) => {
+            let error = manifest_error_response(err);
+            if json {
+                print_json(&ValidateResponse {
+                    ok: false,
+                    manifest: None,
+                    warnings: Vec::new(),
+                    error: Some(error),
+                });
+            } else {
+                print_manifest_error(&error);
+            }
+            1
+        }
+    }
+}
+
+fn planner_error_response(err: PlannerError) -> PlanErrorResponse {
+    let (message, details, environment) = match err {
+        PlannerError::NoCompatibleMode {
+            environment,
+            reasons,
+        } => (
+            "No compatible modes for this environment".to_string(),
+            reasons,
+            Some(*environment),
+        ),
+        PlannerError::IncompatibleMode {
+            mode,
+            environment,
+            reasons,
+            forceable,
+        } => {
+            let hint = if forceable {
+                " (pass --force to use it anyway)"
+            } else {
+                ""
+            };
+            (
+                format!("Mode '{mode}' is not compatible with this environment{hint}"),
+                reasons,
+                Some(*environment),
+            )
+        }
+        err @ (PlannerError::UnknownMode(_) | PlannerError::Vars(_)) => {
+            (err.to_string(), Vec::new(), None)
+        }
+    };
+    PlanErrorResponse {
+        message,
+        details,
+        validation_errors: Vec::new(),
+        environment,
+    }
+}
+
+fn print_plan_warnings(plan: &InstallPlan) {
+    for warning in &plan.warnings {
+        eprintln!("Warning: {warning}");
+    }
+}
+
+fn handle_explain(json: bool, manifest_args: &ManifestArgs, env_args: &EnvArgs) -> i32 {
+    let emit_error = |error: PlanErrorResponse| {
+        if json {
+            print_json(&ExplainResponse {
+                ok: false,
+                environment: None,
+                explanation: None,
+                error: Some(error),
+            });
+        } else {
+            print_manifest_error(&error);
+        }
+    };
+
+    let manifest = match manifest_args.load() {
+        Ok(m) => m,
+        Err(err) => {
+            emit_error(manifest_error_response(err));
+            return 1;
+        }
+    };
+
+    let env = match env_args.resolve(&manifest, &PlanOptions::default()) {
+        Ok(env) => env,
+        Err(err) => {
+            emit_error(PlanErrorResponse {
+                message: format!("{err:#}"),
+                details: Vec::new(),
+                validation_errors: Vec::new(),
+                environment: None,
+            });
+            return 1;
+        }
+    };
+
+    let explanation = explain_modes(&manifest, &env);
+    if json {
+        print_json(&ExplainResponse {
+            ok: true,
+            environment: Some(env),
+            explanation: Some(explanation),
+            error: None,
+        });
+    } else {
+        print_explanation(&explanation);
+    }
+    0
+}
+
+fn handle_plan_matrix(
+    json: bool,
+    manifest_args: &ManifestArgs,
+    envs: &Path,
+    report: ReportFormat,
+    expect: Option<&Path>,
+) -> i32 {
+    let emit_error = |error: PlanErrorResponse| {
+        if json {
+            print_json(&MatrixResponse {
+                ok: false,
+                rows: Vec::new(),
+                assertion_failures: Vec::new(),
+                error: Some(error),
+            });
+        } else {
+            print_manifest_error(&error);
+        }
+    };
+
+    let manifest = match manifest_args.load() {
+        Ok(m) => m,
+        Err(err) => {
+            emit_error(manifest_error_response(err));
+            return 1;
+        }
+    };
+
+    let inputs = load_profiles(envs).and_then(|profiles| {
+        let assertions = expect.map(load_assertions).transpose()?;
+        Ok((profiles, assertions))
+    });
+    let (profiles, assertions) = match inputs {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            emit_error(PlanErrorResponse {
+                message: format!("{err:#}"),
+                details: Vec::new(),
+                validation_errors: Vec::new(),
+                environment: None,
+            });
+            return 1;
+        }
+    };
+
+    let rows = plan_matrix(&manifest, &profiles);
+    let failures = assertions
+        .map(|assertions| check_assertions(&rows, &assertions))
+        .unwrap_or_default();
+
+    let exit_code = if failures.is_empty() { 0 } else { 4 };
+    if json {
+        print_json(&MatrixResponse {
+            ok: failures.is_empty(),
+            rows,
+            assertion_failures: failures,
+            error: None,
+        });
+    } else {
+        print!("{}", render_report(&rows, report));
+        for failure in &failures {
+            eprintln!("Assertion failed: {}", failure.message);
+        }
+    }
+
+    exit_code
+}
+
+fn print_explanation(explanation: &Explanation) {
+    let headers = ["REQUIREMENT", "EXPECTED", "ACTUAL", "RESULT"];
+    for mode in &explanation.modes {
+        let status = match (mode.chosen, mode.compatible) {
+            (true, _) => "compatible, chosen",
+            (false, true) => "compatible",
+            (false, false) => "incompatible",
+        };
+        println!("Mode '{}' ({status})", mode.name);
+
+        let rows: Vec<[&str; 4]> = mode
+            .checks
+            .iter()
+            .map(|check| {
+                [
+                    check.requirement.as_str(),
+                    check.expected.as_str(),
+                    check.actual.as_str(),
+                    check_result(check),
+                ]
+            })
+            .collect();
+        let mut widths = headers.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in std::iter::once(&headers).chain(&rows) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            println!("  {}", line.join("  ").trim_end());
+        }
+        if let Some(reason) = &mode.reason {
+            println!("  Reason: {reason}");
+        }
+        for warning in &mode.warnings {
+            println!("  Warning: {warning}");
+        }
+        println!();
+    }
+
+    match (&explanation.chosen_mode, &explanation.selection_reason) {
+        (Some(mode), Some(reason)) => println!("Chosen mode: {mode} ({reason})"),
+        _ => println!("No compatible mode for this environment."),
+    }
+}
+
+/// The RESULT column: what the check means for the mode, not just whether
+/// it passed.
+fn check_result(check: &RequirementCheck) -> &'static str {
+    match check.outcome {
+        Some(CheckOutcome::Pass) => "pass",
+        Some(CheckOutcome::Fail) => "FAIL",
+        Some(CheckOutcome::Excluded) => "FAIL (not)",
+        Some(CheckOutcome::Warn) => "WARN",
+        Some(CheckOutcome::NotNeeded) => "-",
+        None if check.passed => "pass",
+        None => "FAIL",
+    }
+}
+
+/// Split a manifest loading failure into its headline and, for validation
+/// failures, the individual located issues.
+fn manifest_error_response(err: anyhow::Error) -> PlanErrorResponse {
+    let message = err.to_string();
+    let full_message = format!("{err:#}");
+    match err.downcast::<ManifestValidationErrors>() {
+        Ok(errors) => PlanErrorResponse {
+            message,
+            details: Vec::new(),
+            validation_errors: errors.issues,
+            environment: None,
+        },
+        Err(_) => PlanErrorResponse {
+            message: full_message,
+            details: Vec::new(),
+            validation_errors: Vec::new(),
+            environment: None,
+        },
+    }
+}
+
+fn print_manifest_error(error: &PlanErrorResponse) {
+    eprintln!("{}", error.message);
+    for issue in &error.validation_errors {
+        eprintln!("- {issue}");
+    }
+}
+
+fn handle_schema(json: bool) -> i32 {
+    let schema = manifest_schema();
+    if json {
+        print_json(&SchemaResponse { ok: true, schema });
+    } else {
+        print_json(&schema);
+    }
+    0
+}
+
 fn emit_install_error(
     json: bool,
     plan: Option<&InstallPlan>,
@@ -0,0 +1,1175 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, anyhow};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_RANGE, ETAG, HeaderMap, IF_RANGE, LAST_MODIFIED, RANGE};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use thiserror::Error;
+
+use crate::cache::{DownloadCache, checksum_key, etag_key};
+use crate::manifest::DownloadStep;
+use crate::mirrors::MirrorConfig;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VerificationError {
+    #[error("size mismatch: expected {expected} bytes, got {actual}")]
+    Size { expected: u64, actual: u64 },
+    #[error("{algorithm} mismatch: expected {expected}, got {actual}")]
+    Digest {
+        algorithm: &'static str,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Running size and digests of a download, for the checks its step declares.
+pub struct Digests {
+    sha256: Option<Sha256>,
+    sha512: Option<Sha512>,
+    size: u64,
+}
+
+impl Digests {
+    pub fn for_step(step: &DownloadStep) -> Self {
+        Digests {
+            sha256: step.sha256.as_ref().map(|_| Sha256::new()),
+            sha512: step.sha512.as_ref().map(|_| Sha512::new()),
+            size: 0,
+        }
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        if let Some(hasher) = &mut self.sha256 {
+            hasher.update(chunk);
+        }
+        if let Some(hasher) = &mut self.sha512 {
+            hasher.update(chunk);
+        }
+        self.size += chunk.len() as u64;
+    }
+
+    /// Compare against the step's `size`, `sha256` and `sha512`, in that order.
+    pub fn verify(self, step: &DownloadStep) -> Result<(), VerificationError> {
+        if let Some(expected) = step.size
+            && expected != self.size
+        {
+            return Err(VerificationError::Size {
+                expected,
+                actual: self.size,
+            });
+        }
+
+        let digests = [
+            (
+                "sha256",
+                &step.sha256,
+                self.sha256.map(|h| format!("{:x}", h.finalize())),
+            ),
+            (
+                "sha512",
+                &step.sha512,
+                self.sha512.map(|h| format!("{:x}", h.finalize())),
+            ),
+        ];
+        for (algorithm, expected, actual) in digests {
+            if let (Some(expected), Some(actual)) = (expected, actual)
+                && !expected.eq_ignore_ascii_case(&actual)
+            {
+                return Err(VerificationError::Digest {
+                    algorithm,
+                    expected: expected.to_ascii_lowercase(),
+                    actual,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the step declares a size or digest to check.
+pub fn has_checks(step: &DownloadStep) -> bool {
+    step.size.is_some() || step.sha256.is_some() || step.sha512.is_some()
+}
+
+/// Check a file on disk against the step's declared size and digests.
+pub fn verify_file(step: &DownloadStep, path: &Path) -> anyhow::Result<()> {
+    let mut digests = Digests::for_step(step);
+    hash_file(path, &mut digests)?;
+    digests
+        .verify(step)
+        .with_context(|| format!("verifying {}", path.display()))
+}
+
+fn hash_file(path: &Path, digests: &mut Digests) -> anyhow::Result<()> {
+    let mut file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut buf)
+            .with_context(|| format!("reading {}", path.display()))?;
+        if read == 0 {
+            return Ok(());
+        }
+        digests.update(&buf[..read]);
+    }
+}
+
+/// Byte-level progress of one download, reported after every chunk.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress<'a> {
+    pub url: &'a str,
+    pub dest: &'a Path,
+    /// Bytes in the file so far, including any resumed from an earlier attempt.
+    pub downloaded: u64,
+    /// Expected size, from the step's `size` or the response length.
+    pub total: Option<u64>,
+    /// Set on the last event, once the file is verified and in place.
+    pub finished: bool,
+    /// Set on the last event when the download from this URL gave up.
+    pub failed: bool,
+}
+
+/// Receives [`DownloadProgress`] events; shared so embedders can forward
+/// them to another thread.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&DownloadProgress<'_>) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(&DownloadProgress<'_>) + Send + Sync + 'static) -> Self {
+        ProgressCallback(Arc::new(callback))
+    }
+
+    fn report(&self, progress: &DownloadProgress<'_>) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Retry and timeout settings for downloads.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Further attempts after the first one fails with a transient error.
+    pub retries: u32,
+    /// Delay before the first retry; each later retry waits twice as long.
+    pub backoff: Duration,
+    pub connect_timeout: Duration,
+    /// Longest wait for the response headers or for any single read of the body.
+    pub read_timeout: Duration,
+    pub progress: Option<ProgressCallback>,
+    /// URL prefix rewrites tried before the step's own URLs.
+    pub mirrors: MirrorConfig,
+    /// Shared store consulted before the network and filled after each
+    /// successful download.
+    pub cache: Option<DownloadCache>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            connect_timeout: Duration::from_secs(15),
+            read_timeout: Duration::from_secs(60),
+            progress: None,
+            mirrors: MirrorConfig::default(),
+            cache: None,
+        }
+    }
+}
+
+/// Why one download attempt failed, and whether another attempt may help.
+enum AttemptError {
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Where a download step's file came from, for execution results and the
+/// install record.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DownloadRecord {
+    pub step_index: usize,
+    pub url: String,
+    pub dest: PathBuf,
+    /// Set when the file was restored from the download cache; `url` is then
+    /// where the cached copy was originally downloaded from.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+/// Where [`perform_download`] got a step's file from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadSource {
+    pub url: String,
+    pub cached: bool,
+}
+
+/// Download the step's file to `step.dest`, trying each source URL in order
+/// (with configured mirrors before the URLs they rewrite) and returning the
+/// URL that succeeded. The body is streamed into a `.part` file next to
+/// `dest` and only renamed into place once it passes the step's checks.
+/// Transient failures (connection errors, timeouts, 5xx responses) are
+/// retried with exponential backoff before moving on to the next URL,
+/// resuming from the bytes already in the `.part` file with an HTTP `Range`
+/// request, including a `.part` file left by an earlier run.
+///
+/// With a cache configured, a step with a digest is restored from the cache
+/// without touching the network, and a step without one is restored when
+/// the server still reports the ETag the cached copy was downloaded with.
+/// Successful downloads are added to the cache under the same keys.
+///
+/// An existing `dest` is reused when it passes the step's checks, returning
+/// `None`, and is an error when it does not; without checks it is
+/// overwritten.
+pub fn perform_download(
+    step: &DownloadStep,
+    options: &DownloadOptions,
+) -> anyhow::Result<Option<DownloadSource>> {
+    if has_checks(step) && step.dest.exists() {
+        verify_file(step, &step.dest).with_context(|| {
+            format!(
+                "existing file {} is not the expected download",
+                step.dest.display()
+            )
+        })?;
+        println!(
+            "    {} is already present and verified",
+            step.dest.display()
+        );
+        return Ok(None);
+    }
+
+    let urls = options.mirrors.candidates(&step.sources());
+    if urls.is_empty() {
+        return Err(anyhow!("download step has no url"));
+    }
+
+    if let Some(parent) = step.dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part_path = part_path(&step.dest);
+
+    let checksum_key = checksum_key(step);
+    if let (Some(cache), Some(key)) = (&options.cache, &checksum_key)
+        && let Some(url) = restore_from_cache(cache, key, step, &part_path, options)
+    {
+        return Ok(Some(DownloadSource { url, cached: true }));
+    }
+
+    let client = Client::builder()
+        .connect_timeout(options.connect_timeout)
+        .timeout(options.read_timeout)
+        .build()
+        .context("building HTTP client")?;
+
+    let mut failures = Vec::new();
+    for url in &urls {
+        if let Some(cache) = &options.cache
+            && checksum_key.is_none()
+            && cache.has_url(url)
+            && let Some(etag) = current_etag(&client, url)
+            && let Some(cached_url) =
+                restore_from_cache(cache, &etag_key(url, &etag), step, &part_path, options)
+        {
+            return Ok(Some(DownloadSource {
+                url: cached_url,
+                cached: true,
+            }));
+        }
+
+        match download_from(&client, step, url, &part_path, options) {
+            Ok(etag) => {
+                if let Some(cache) = &options.cache {
+                    let key = checksum_key
+                        .clone()
+                        .or_else(|| etag.as_deref().map(|etag| etag_key(url, etag)));
+                    if let Some(key) = key
+                        && let Err(err) = cache.insert(&key, &step.dest, url, etag)
+                    {
+                        println!(
+                            "    could not add {} to the download cache: {err:#}",
+                            step.dest.display()
+                        );
+                    }
+                }
+                return Ok(Some(DownloadSource {
+                    url: url.clone(),
+                    cached: false,
+                }));
+            }
+            Err(err) if urls.len() == 1 => return Err(err),
+            Err(err) => {
+                println!("    {url} failed: {err:#}");
+                failures.push(format!("{url}: {err:#}"));
+                // Without checks, bytes from another URL cannot be trusted to
+                // belong to the same file.
+                if !has_checks(step) {
+                    remove_part(&part_path);
+                }
+            }
+        }
+    }
+    Err(anyhow!(
+        "all {} download URLs failed: {}",
+        urls.len(),
+        failures.join("; ")
+    ))
+}
+
+/// Copy the cache entry for `key` into place, returning the URL it was
+/// downloaded from. A cache that cannot be read counts as a miss, so `None`
+/// means the file should be downloaded.
+fn restore_from_cache(
+    cache: &DownloadCache,
+    key: &str,
+    step: &DownloadStep,
+    part_path: &Path,
+    options: &DownloadOptions,
+) -> Option<String> {
+    match copy_from_cache(cache, key, step, part_path, options) {
+        Ok(url) => url,
+        Err(err) => {
+            println!("    could not use the download cache, downloading instead: {err:#}");
+            remove_part(part_path);
+            None
+        }
+    }
+}
+
+/// The fallible part of [`restore_from_cache`]. An entry that fails the
+/// step's checks is dropped from the cache.
+fn copy_from_cache(
+    cache: &DownloadCache,
+    key: &str,
+    step: &DownloadStep,
+    part_path: &Path,
+    options: &DownloadOptions,
+) -> anyhow::Result<Option<String>> {
+    let Some((entry, object)) = cache.get(key)? else {
+        return Ok(None);
+    };
+    fs::copy(&object, part_path)
+        .with_context(|| format!("copying cached {} to {}", key, part_path.display()))?;
+    if let Err(err) = verify_file(step, part_path) {
+        println!("    cached copy is corrupt, downloading again: {err:#}");
+        remove_part(part_path);
+        cache.remove(key)?;
+        return Ok(None);
+    }
+    fs::rename(part_path, &step.dest)
+        .with_context(|| format!("moving download into place at {}", step.dest.display()))?;
+    println!(
+        "    restored {} from the download cache",
+        step.dest.display()
+    );
+    if let Some(progress) = &options.progress {
+        progress.report(&DownloadProgress {
+            url: &entry.url,
+            dest: &step.dest,
+            downloaded: entry.size,
+            total: Some(entry.size),
+            finished: true,
+            failed: false,
+        });
+    }
+    Ok(Some(entry.url))
+}
+
+/// The ETag the server currently reports for `url`, from a `HEAD` request.
+fn current_etag(client: &Client, url: &str) -> Option<String> {
+    let response = client.head(url).send().ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response
+        .headers()
+        .get(ETAG)?
+        .to_str()
+        .ok()
+        .map(str::to_string)
+}
+
+/// Download from one URL with retries, then verify and move the file into
+/// place, returning the response's ETag. The last progress event reports
+/// whether it finished or failed.
+fn download_from(
+    client: &Client,
+    step: &DownloadStep,
+    url: &str,
+    part_path: &Path,
+    options: &DownloadOptions,
+) -> anyhow::Result<Option<String>> {
+    let result = fetch_into_place(client, step, url, part_path, options);
+    if let Some(progress) = &options.progress {
+        let downloaded = match &result {
+            Ok((downloaded, _)) => *downloaded,
+            Err(_) => fs::metadata(part_path).map(|meta| meta.len()).unwrap_or(0),
+        };
+        progress.report(&DownloadProgress {
+            url,
+            dest: &step.dest,
+            downloaded,
+            total: result.is_ok().then_some(downloaded),
+            finished: result.is_ok(),
+            failed: result.is_err(),
+        });
+    }
+    result.map(|(_, etag)| etag)
+}
+
+/// The retrying, verifying part of [`download_from`], returning the size of
+/// the file and the response's ETag. A file that fails its checks after
+/// resuming an existing `.part` file is downloaded once more from the start,
+/// since the part may be left over from an older version of the resource.
+fn fetch_into_place(
+    client: &Client,
+    step: &DownloadStep,
+    url: &str,
+    part_path: &Path,
+    options: &DownloadOptions,
+) -> anyhow::Result<(u64, Option<String>)> {
+    let had_part = fs::metadata(part_path).is_ok_and(|meta| meta.len() > 0);
+    let attempts = options.retries + 1;
+    let mut attempt = 0;
+    let (digests, etag) = loop {
+        attempt += 1;
+        println!("    attempt {attempt} of {attempts}: {url}");
+        match download_attempt(client, step, url, part_path, options.progress.as_ref()) {
+            Ok(done) => break done,
+            Err(AttemptError::Transient(err)) if attempt < attempts => {
+                let delay = options.backoff * 2u32.saturating_pow(attempt - 1);
+                println!("    attempt {attempt} failed: {err:#}; retrying in {delay:?}");
+                thread::sleep(delay);
+            }
+            Err(AttemptError::Transient(err)) => {
+                return Err(err.context(format!("giving up after {attempt} attempts")));
+            }
+            Err(AttemptError::Fatal(err)) => return Err(err),
+        }
+    };
+
+    let downloaded = digests.size;
+    if let Err(err) = digests.verify(step) {
+        remove_part(part_path);
+        if had_part {
+            println!("    resumed download failed its checks ({err}); starting over");
+            return fetch_into_place(client, step, url, part_path, options);
+        }
+        return Err(anyhow!(err).context(format!("verifying download from {url}")));
+    }
+
+    fs::rename(part_path, &step.dest)
+        .with_context(|| format!("moving download into place at {}", step.dest.display()))?;
+    let _ = fs::remove_file(validator_path(part_path));
+    Ok((downloaded, etag))
+}
+
+/// Request the bytes not yet in `part_path` and append them, returning the
+/// digests of the whole file and the response's ETag.
+///
+/// A resume sends `If-Range` with the validator recorded when the `.part`
+/// file was started, so a resource that changed since is sent whole rather
+/// than appended to old bytes. Without a validator, a `.part` file is only
+/// resumed when the step's checks will catch a mismatch.
+fn download_attempt(
+    client: &Client,
+    step: &DownloadStep,
+    url: &str,
+    part_path: &Path,
+    progress: Option<&ProgressCallback>,
+) -> Result<(Digests, Option<String>), AttemptError> {
+    let fatal = |err: anyhow::Error| AttemptError::Fatal(err);
+    let mut have = fs::metadata(part_path).map(|meta| meta.len()).unwrap_or(0);
+    let if_range = if have > 0 {
+        load_validator(part_path).and_then(|validator| validator.if_range())
+    } else {
+        None
+    };
+    if have > 0 && if_range.is_none() && !has_checks(step) {
+        println!("    discarding partial download that cannot be checked");
+        remove_part(part_path);
+        have = 0;
+    }
+
+    let mut request = client.get(url);
+    if have > 0 {
+        request = request.header(RANGE, format!("bytes={have}-"));
+        if let Some(validator) = &if_range {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+    let mut response = request
+        .send()
+        .with_context(|| format!("requesting {url}"))
+        .map_err(AttemptError::Transient)?;
+
+    let status = response.status();
+    let resumed = status == StatusCode::PARTIAL_CONTENT
+        && response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|range| range.starts_with(&format!("bytes {have}-")));
+    if status == StatusCode::RANGE_NOT_SATISFIABLE
+        || (status == StatusCode::PARTIAL_CONTENT && !resumed)
+    {
+        // The partial file no longer lines up with the resource; start over.
+        remove_part(part_path);
+        return Err(AttemptError::Transient(anyhow!(
+            "server could not resume {url} from byte {have}"
+        )));
+    }
+    if !status.is_success() {
+        let err = anyhow!("download failed with status {status}");
+        return Err(
+            if status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS
+            {
+                AttemptError::Transient(err)
+            } else {
+                AttemptError::Fatal(err)
+            },
+        );
+    }
+
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let mut digests = Digests::for_step(step);
+    let mut part_file = if resumed {
+        println!("    resuming from byte {have}");
+        hash_file(part_path, &mut digests).map_err(fatal)?;
+        OpenOptions::new().append(true).open(part_path)
+    } else {
+        save_validator(part_path, &PartValidator::from_headers(response.headers()))
+            .map_err(fatal)?;
+        File::create(part_path)
+    }
+    .with_context(|| format!("creating destination file {}", part_path.display()))
+    .map_err(fatal)?;
+
+    let total = step.size.or_else(|| {
+        response
+            .content_length()
+            .map(|len| len + if resumed { have } else { 0 })
+    });
+    let report = |downloaded| {
+        if let Some(progress) = progress {
+            progress.report(&DownloadProgress {
+                url,
+                dest: &step.dest,
+                downloaded,
+                total,
+                finished: false,
+                failed: false,
+            });
+        }
+    };
+    report(digests.size);
+
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = response
+            .read(&mut buf)
+            .with_context(|| format!("reading response from {url}"))
+            .map_err(AttemptError::Transient)?;
+        if read == 0 {
+            break;
+        }
+        digests.update(&buf[..read]);
+        part_file
+            .write_all(&buf[..read])
+            .with_context(|| format!("writing {}", part_path.display()))
+            .map_err(fatal)?;
+        report(digests.size);
+    }
+    part_file
+        .flush()
+        .with_context(|| format!("writing {}", part_path.display()))
+        .map_err(fatal)?;
+
+    Ok((digests, etag))
+}
+
+/// `dest` with `.part` appended to its file name.
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Where the validator of a `.part` file is kept, next to it.
+fn validator_path(part_path: &Path) -> PathBuf {
+    let mut name = part_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".json");
+    part_path.with_file_name(name)
+}
+
+fn remove_part(part_path: &Path) {
+    let _ = fs::remove_file(part_path);
+    let _ = fs::remove_file(validator_path(part_path));
+}
+
+/// The response validators of the resource a `.part` file was started from.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+struct PartValidator {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+impl PartValidator {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        PartValidator {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// The `If-Range` value: a strong ETag, else the modification date. Weak
+    /// ETags cannot be used for range requests.
+    fn if_range(self) -> Option<String> {
+        self.etag
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified)
+    }
+}
+
+fn load_validator(part_path: &Path) -> Option<PartValidator> {
+    let data = fs::read_to_string(validator_path(part_path)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+fn save_validator(part_path: &Path, validator: &PartValidator) -> anyhow::Result<()> {
+    let path = validator_path(part_path);
+    if *validator == PartValidator::default() {
+        let _ = fs::remove_file(&path);
+        return Ok(());
+    }
+    fs::write(&path, serde_json::to_string(validator)?)
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tiny_http::{Header, Response, Server, StatusCode};
+
+    use super::{
+        Digests, DownloadOptions, ProgressCallback, VerificationError, part_path, perform_download,
+        validator_path, verify_file,
+    };
+    use crate::cache::DownloadCache;
+    use crate::manifest::DownloadStep;
+    use crate::mirrors::MirrorConfig;
+
+    // SHA-256 and SHA-512 of "hello world".
+    const HELLO_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    const HELLO_SHA512: &str = "309ecc489c12d6eb4cc40f50c902f2b4d0ed77ee511a7c7a9bcd3ca86d4cd86f\
+                                989dd35bc5ff499670da34255b45b0cfd830e81f605dcf7dc5542e93ae9cd76f";
+
+    fn step(dest: &Path) -> DownloadStep {
+        DownloadStep {
+            // Unroutable, so a test that reaches the network fails.
+            url: "http://127.0.0.1:9/artifact.zip".into(),
+            urls: vec![],
+            dest: dest.to_path_buf(),
+            sha256: None,
+            sha512: None,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn verifies_size_and_digests() {
+        let mut step = step(Path::new("unused"));
+        step.size = Some(11);
+        step.sha256 = Some(HELLO_SHA256.to_uppercase());
+        step.sha512 = Some(HELLO_SHA512.into());
+
+        let mut digests = Digests::for_step(&step);
+        digests.update(b"hello ");
+        digests.update(b"world");
+        assert_eq!(digests.verify(&step), Ok(()));
+
+        let mut digests = Digests::for_step(&step);
+        digests.update(b"hello");
+        assert_eq!(
+            digests.verify(&step),
+            Err(VerificationError::Size {
+                expected: 11,
+                actual: 5
+            })
+        );
+
+        step.size = None;
+        let mut digests = Digests::for_step(&step);
+        digests.update(b"hello wor1d");
+        let err = digests.verify(&step).unwrap_err();
+        assert!(matches!(
+            err,
+            VerificationError::Digest {
+                algorithm: "sha256",
+                ..
+            }
+        ));
+        assert!(
+            err.to_string()
+                .starts_with(&format!("sha256 mismatch: expected {HELLO_SHA256}, got "))
+        );
+    }
+
+    #[test]
+    fn reuses_a_verified_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        std::fs::write(&dest, "hello world").unwrap();
+        let mut step = step(&dest);
+        step.sha256 = Some(HELLO_SHA256.into());
+
+        perform_download(&step, &options()).expect("existing file should be reused");
+        assert!(verify_file(&step, &dest).is_ok());
+    }
+
+    #[test]
+    fn rejects_an_existing_file_that_does_not_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        std::fs::write(&dest, "hello").unwrap();
+        let mut step = step(&dest);
+        step.sha256 = Some(HELLO_SHA256.into());
+
+        let err = perform_download(&step, &options()).unwrap_err();
+        let message = format!("{err:#}");
+        assert!(
+            message.contains("is not the expected download"),
+            "{message}"
+        );
+        assert!(message.contains(&format!("expected {HELLO_SHA256}, got ")));
+    }
+
+    /// How the test server answers one request.
+    #[derive(Clone, Copy)]
+    enum Reply {
+        /// Serve the body, honouring `Range`.
+        Body,
+        /// Serve the body but ignore `Range`.
+        FullBody,
+        /// Announce the full length but send only this many bytes, then stall.
+        Stall(usize),
+        Status(u16),
+    }
+
+    /// The `Range` and `If-Range` headers of a request.
+    type Seen = (Option<String>, Option<String>);
+
+    /// Serve `body` on a local port, answering request `n` with `replies(n)`.
+    /// Returns the URL and the `Range` and `If-Range` headers of each request
+    /// received.
+    fn serve(body: &'static [u8], replies: fn(usize) -> Reply) -> (String, Arc<Mutex<Vec<Seen>>>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&ranges);
+        std::thread::spawn(move || {
+            for (idx, request) in server.incoming_requests().enumerate() {
+                let header = |name| {
+                    request
+                        .headers()
+                        .iter()
+                        .find(|header| header.field.equiv(name))
+                        .map(|header| header.value.to_string())
+                };
+                let (range, if_range) = (header("Range"), header("If-Range"));
+                seen.lock().unwrap().push((range.clone(), if_range.clone()));
+
+                // A stale If-Range validator gets the whole, current body.
+                let start = range
+                    .as_deref()
+                    .filter(|_| if_range.as_deref().is_none_or(|tag| tag == "\"v1\""))
+                    .and_then(|range| range.strip_prefix("bytes="))
+                    .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+                let (status, start) = match (replies(idx), start) {
+                    (Reply::Status(code), _) => {
+                        let _ = request.respond(Response::empty(code));
+                        continue;
+                    }
+                    (Reply::Body | Reply::Stall(_), Some(start)) => (206, start),
+                    _ => (200, 0),
+                };
+                let rest = &body[start..];
+                let sent = match replies(idx) {
+                    Reply::Stall(len) => &rest[..len],
+                    _ => rest,
+                };
+                let mut headers = vec![Header::from_bytes("ETag", "\"v1\"").unwrap()];
+                if status == 206 {
+                    let range = format!("bytes {start}-{}/{}", body.len() - 1, body.len());
+                    headers.push(Header::from_bytes("Content-Range", range).unwrap());
+                }
+                let response = Response::new(
+                    StatusCode(status),
+                    headers,
+                    Cursor::new(sent),
+                    Some(rest.len()),
+                    None,
+                );
+                let _ = request.respond(response);
+            }
+        });
+        (format!("http://127.0.0.1:{port}/artifact.zip"), ranges)
+    }
+
+    fn options() -> DownloadOptions {
+        DownloadOptions {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_millis(300),
+            progress: None,
+            mirrors: MirrorConfig::default(),
+            cache: None,
+        }
+    }
+
+    #[test]
+    fn resumes_a_stalled_download_with_a_range_request() {
+        let (url, ranges) = serve(b"hello world", |n| match n {
+            0 => Reply::Stall(5),
+            _ => Reply::Body,
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("nested/artifact.zip");
+        let mut step = step(&dest);
+        step.url = url;
+        step.sha256 = Some(HELLO_SHA256.into());
+
+        perform_download(&step, &options()).expect("download should resume");
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello world");
+        assert!(!part_path(&dest).exists());
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            vec![
+                (None, None),
+                (Some("bytes=5-".to_string()), Some("\"v1\"".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_progress_through_the_callback() {
+        let (url, _) = serve(b"hello world", |n| match n {
+            0 => Reply::Stall(5),
+            _ => Reply::Body,
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let mut step = step(&dir.path().join("artifact.zip"));
+        step.url = url;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+        let options = DownloadOptions {
+            progress: Some(ProgressCallback::new(move |progress| {
+                seen.lock()
+                    .unwrap()
+                    .push((progress.downloaded, progress.total, progress.finished));
+            })),
+            ..options()
+        };
+        perform_download(&step, &options).expect("download should succeed");
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (0, Some(11), false),
+                (5, Some(11), false),
+                (5, Some(11), false),
+                (11, Some(11), false),
+                (11, Some(11), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_a_failed_event_when_a_url_gives_up() {
+        let (url, _) = serve(b"hello world", |_| Reply::Status(404));
+        let dir = tempfile::tempdir().unwrap();
+        let mut step = step(&dir.path().join("artifact.zip"));
+        step.url = url;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+        let options = DownloadOptions {
+            progress: Some(ProgressCallback::new(move |progress| {
+                seen.lock()
+                    .unwrap()
+                    .push((progress.finished, progress.failed));
+            })),
+            ..options()
+        };
+        assert!(perform_download(&step, &options).is_err());
+        assert_eq!(*events.lock().unwrap(), vec![(false, true)]);
+    }
+
+    #[test]
+    fn restarts_when_the_server_ignores_the_range() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::FullBody);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        std::fs::write(part_path(&dest), "stale").unwrap();
+        let mut step = step(&dest);
+        step.url = url;
+        step.size = Some(11);
+
+        perform_download(&step, &options()).expect("download should restart");
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello world");
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            vec![(Some("bytes=5-".to_string()), None)]
+        );
+    }
+
+    #[test]
+    fn restarts_a_stale_checked_part_without_a_validator() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        std::fs::write(part_path(&dest), "HELLO").unwrap();
+        let mut step = step(&dest);
+        step.url = url;
+        step.sha256 = Some(HELLO_SHA256.into());
+
+        perform_download(&step, &options()).expect("download should start over");
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello world");
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            vec![(Some("bytes=5-".to_string()), None), (None, None)]
+        );
+    }
+
+    #[test]
+    fn discards_unchecked_parts_without_a_validator() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        std::fs::write(part_path(&dest), "stale").unwrap();
+        let mut step = step(&dest);
+        step.url = url;
+
+        perform_download(&step, &options()).expect("download should restart");
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello world");
+        assert_eq!(*ranges.lock().unwrap(), vec![(None, None)]);
+    }
+
+    #[test]
+    fn restarts_when_the_validator_no_longer_matches() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        let part = part_path(&dest);
+        std::fs::write(&part, "stale").unwrap();
+        std::fs::write(validator_path(&part), r#"{"etag":"\"v0\""}"#).unwrap();
+        let mut step = step(&dest);
+        step.url = url;
+
+        perform_download(&step, &options()).expect("download should restart");
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello world");
+        assert!(!validator_path(&part).exists());
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            vec![(Some("bytes=5-".to_string()), Some("\"v0\"".to_string()))]
+        );
+    }
+
+    #[test]
+    fn retries_server_errors_then_gives_up() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Status(503));
+        let dir = tempfile::tempdir().unwrap();
+        let mut step = step(&dir.path().join("artifact.zip"));
+        step.url = url;
+
+        let err = perform_download(&step, &options()).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "giving up after 3 attempts: download failed with status 503 Service Unavailable"
+        );
+        assert_eq!(ranges.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Status(404));
+        let dir = tempfile::tempdir().unwrap();
+        let mut step = step(&dir.path().join("artifact.zip"));
+        step.url = url;
+
+        let err = perform_download(&step, &options()).unwrap_err();
+        assert_eq!(err.to_string(), "download failed with status 404 Not Found");
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn falls_back_to_the_next_url() {
+        let (missing, _) = serve(b"", |_| Reply::Status(404));
+        let (url, _) = serve(b"hello world", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        let mut step = step(&dest);
+        step.url = missing;
+        step.urls = vec![url.clone()];
+
+        let used = perform_download(&step, &options()).expect("fallback should succeed");
+        assert_eq!(used.map(|source| source.url), Some(url));
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn tries_a_configured_mirror_first() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Body);
+        let mirror = url.trim_end_matches("artifact.zip").to_string();
+        let dir = tempfile::tempdir().unwrap();
+        let mut step = step(&dir.path().join("artifact.zip"));
+        step.url = "https://cdn.invalid/app/artifact.zip".into();
+        let options = DownloadOptions {
+            mirrors: MirrorConfig {
+                mirrors: [("https://cdn.invalid/app/".to_string(), mirror)].into(),
+            },
+            ..options()
+        };
+
+        let used = perform_download(&step, &options).expect("mirror should succeed");
+        assert_eq!(used.map(|source| source.url), Some(url));
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reports_every_failed_url() {
+        let (first, _) = serve(b"", |_| Reply::Status(404));
+        let (second, _) = serve(b"", |_| Reply::Status(410));
+        let dir = tempfile::tempdir().unwrap();
+        let mut step = step(&dir.path().join("artifact.zip"));
+        step.url = first.clone();
+        step.urls = vec![second.clone()];
+
+        let err = perform_download(&step, &options()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "all 2 download URLs failed: {first}: download failed with status 404 Not Found; \
+                 {second}: download failed with status 410 Gone"
+            )
+        );
+    }
+
+    #[test]
+    fn discards_a_download_that_fails_verification() {
+        let (url, _) = serve(b"hello wor1d", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("artifact.zip");
+        let mut step = step(&dest);
+        step.url = url;
+        step.sha256 = Some(HELLO_SHA256.into());
+
+        let err = perform_download(&step, &options()).unwrap_err();
+        assert!(format!("{err:#}").contains("sha256 mismatch"));
+        assert!(!dest.exists());
+        assert!(!part_path(&dest).exists());
+    }
+
+    #[test]
+    fn restores_checksummed_downloads_from_the_cache() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let options = DownloadOptions {
+            cache: Some(DownloadCache::new(dir.path().join("cache"), 1 << 20)),
+            ..options()
+        };
+        let mut first = step(&dir.path().join("one/artifact.zip"));
+        first.url = url.clone();
+        first.sha256 = Some(HELLO_SHA256.into());
+        let used = perform_download(&first, &options).expect("download should succeed");
+        assert_eq!(used.map(|source| source.cached), Some(false));
+
+        // Same digest, unreachable URL: only the cache can satisfy it.
+        let mut second = step(&dir.path().join("two/artifact.zip"));
+        second.sha256 = Some(HELLO_SHA256.into());
+        let used = perform_download(&second, &options).expect("cache should satisfy the step");
+        let used = used.expect("file should come from the cache");
+        assert!(used.cached);
+        assert_eq!(used.url, url);
+        assert_eq!(std::fs::read(&second.dest).unwrap(), b"hello world");
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn downloads_when_the_cache_cannot_be_read() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("cache");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("index.json"), "not json").unwrap();
+        let options = DownloadOptions {
+            cache: Some(DownloadCache::new(root, 1 << 20)),
+            ..options()
+        };
+        let mut step = step(&dir.path().join("artifact.zip"));
+        step.url = url;
+        step.sha256 = Some(HELLO_SHA256.into());
+
+        let used = perform_download(&step, &options).expect("download should ignore the cache");
+        assert_eq!(used.map(|source| source.cached), Some(false));
+        assert_eq!(std::fs::read(&step.dest).unwrap(), b"hello world");
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn restores_unchecked_downloads_while_the_etag_matches() {
+        let (url, ranges) = serve(b"hello world", |_| Reply::Body);
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(dir.path().join("cache"), 1 << 20);
+        let options = DownloadOptions {
+            cache: Some(cache.clone()),
+            ..options()
+        };
+        let mut first = step(&dir.path().join("one/artifact.zip"));
+        first.url = url.clone();
+        perform_download(&first, &options).expect("download should succeed");
+        assert_eq!(cache.entries().unwrap()[0].etag.as_deref(), Some("\"v1\""));
+
+        let mut second = step(&dir.path().join("two/artifact.zip"));
+        second.url = url;
+        let used = perform_download(&second, &options).expect("cache should satisfy the step");
+        assert_eq!(used.map(|source| source.cached), Some(true));
+        assert_eq!(std::fs::read(&second.dest).unwrap(), b"hello world");
+        // The download and the HEAD request that confirmed the ETag.
+        assert_eq!(ranges.lock().unwrap().len(), 2);
+    }
+}
@@ -1,23 +1,82 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::process::Command;
 
-use serde::Serialize;
-use sysinfo::{System, SystemExt};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sysinfo::{Disks, System};
 use which::which;
 
 use sha2::{Digest, Sha256};
 
-#[derive(Debug, Clone, Serialize)]
+/// A machine profile, either detected or loaded from a file saved with
+/// `detect --save` to plan for a machine other than this one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Environment {
     pub os: String,
+    /// Numeric dotted version used for matching: the product version on
+    /// macOS, `major.0.build` on Windows, and the kernel version on Linux.
     pub os_version: String,
+    /// Human-readable version as reported by the OS, e.g. "macOS 14.0 Sonoma".
+    #[serde(default)]
+    pub os_version_display: String,
     pub cpu_arch: String,
     pub ram_gb: u64,
+    /// Logical CPU cores; `None` when unknown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_cores: Option<u64>,
+    /// Detected instruction set extensions, e.g. `avx2`, `sse4_2`, `neon`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu_features: Vec<String>,
+    /// Free space, in GiB, on the filesystem holding the current directory.
+    /// Used for modes without an entry in `disk_free_gb_by_mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_free_gb: Option<u64>,
+    /// Free space, in GiB, at each mode's install target (see
+    /// `planner::install_target`), keyed by mode name. Filled in by the CLI
+    /// for the manifest being planned.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub disk_free_gb_by_mode: BTreeMap<String, u64>,
+    #[serde(default)]
     pub pkg_managers: Vec<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Commands found on `PATH`, mapped to the version parsed from their
+    /// `--version` output ("unknown" when it failed or had no version). Only the
+    /// commands a manifest requires, or that `detect --probe` names, are
+    /// probed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distro_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distro_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub id_like: Vec<String>,
+    /// C library family on Linux: `glibc` or `musl`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub libc: Option<String>,
+    /// Version of the C library, when it could be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub libc_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub fingerprint: Option<Fingerprint>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl Environment {
+    /// This environment as mode `name` sees it, with `disk_free_gb` measured
+    /// at that mode's install target when known.
+    pub fn for_mode(&self, name: &str) -> Cow<'_, Environment> {
+        match self.disk_free_gb_by_mode.get(name) {
+            Some(&free) if self.disk_free_gb != Some(free) => Cow::Owned(Environment {
+                disk_free_gb: Some(free),
+                ..self.clone()
+            }),
+            _ => Cow::Borrowed(self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Fingerprint {
     pub os: String,
     pub os_version: String,
@@ -28,31 +87,105 @@ pub struct Fingerprint {
     pub hash: String,
 }
 
+/// The subset of `/etc/os-release` used for distro-aware OS constraints.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OsRelease {
+    pub id: Option<String>,
+    pub version_id: Option<String>,
+    pub id_like: Vec<String>,
+}
+
 pub fn detect_environment() -> anyhow::Result<Environment> {
     let mut system = System::new_all();
     system.refresh_all();
 
     let os = normalize_os(std::env::consts::OS);
-    let os_version = system
-        .long_os_version()
-        .or_else(|| system.os_version())
+    let os_version_display = System::long_os_version()
+        .or_else(System::os_version)
+        .unwrap_or_else(|| "unknown".to_string());
+    let numeric_version = if os == "linux" {
+        System::kernel_version()
+    } else {
+        System::os_version()
+    };
+    let os_version = numeric_version
+        .and_then(|raw| normalize_os_version(&os, &raw))
+        .or_else(|| normalize_os_version(&os, &os_version_display))
         .unwrap_or_else(|| "unknown".to_string());
     let cpu_arch = normalize_arch(std::env::consts::ARCH);
-    let ram_gb = system.total_memory() / 1_048_576; // KiB to GiB
+    let ram_gb = system.total_memory() / 1_073_741_824; // bytes to GiB
+    let cpu_cores = Some(system.cpus().len() as u64).filter(|&cores| cores > 0);
+    let disk_free_gb = std::env::current_dir()
+        .ok()
+        .and_then(|dir| disk_free_gb_at(&dir));
     let pkg_managers = detect_package_managers(&os);
+    let (os_release, libc) = if os == "linux" {
+        (read_os_release(), detect_libc())
+    } else {
+        (OsRelease::default(), None)
+    };
+    let (libc, libc_version) = match libc {
+        Some((family, version)) => (Some(family), version),
+        None => (None, None),
+    };
 
-    let fingerprint = compute_fingerprint(&os, &os_version, &cpu_arch, ram_gb, system.host_name());
+    // Hash the display string so fingerprints stay stable across releases
+    // that change version normalization.
+    let fingerprint = compute_fingerprint(
+        &os,
+        &os_version_display,
+        &cpu_arch,
+        ram_gb,
+        System::host_name(),
+    );
 
     Ok(Environment {
         os,
         os_version,
+        os_version_display,
         cpu_arch,
         ram_gb,
+        cpu_cores,
+        cpu_features: detect_cpu_features(),
+        disk_free_gb,
+        disk_free_gb_by_mode: BTreeMap::new(),
         pkg_managers,
+        commands: BTreeMap::new(),
+        distro_id: os_release.id,
+        distro_version: os_release.version_id,
+        id_like: os_release.id_like,
+        libc,
+        libc_version,
         fingerprint: Some(fingerprint),
     })
 }
 
+/// Load an environment profile previously written by `detect --save`.
+pub fn load_environment(path: &Path) -> anyhow::Result<Environment> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading environment file at {}", path.display()))?;
+    let mut env: Environment = serde_json::from_str(&contents)
+        .with_context(|| format!("parsing environment file at {}", path.display()))?;
+
+    // Profiles saved before normalization carry the display string in
+    // `os_version`.
+    if env.os_version_display.is_empty() {
+        env.os_version_display = env.os_version.clone();
+    }
+    if let Some(normalized) = normalize_os_version(&env.os, &env.os_version) {
+        env.os_version = normalized;
+    }
+
+    Ok(env)
+}
+
+/// Write `env` as pretty-printed JSON so it can be replayed with `--env`.
+pub fn save_environment(env: &Environment, path: &Path) -> anyhow::Result<()> {
+    let contents = serde_json::to_string_pretty(env)?;
+    std::fs::write(path, contents)
+        .with_context(|| format!("writing environment file at {}", path.display()))
+}
+
 fn compute_fingerprint(
     os: &str,
     os_version: &str,
@@ -81,10 +214,138 @@ fn compute_fingerprint(
     }
 }
 
+/// Extract a numeric dotted version from an OS version string such as
+/// "macOS 14.0 Sonoma", "6.5.0-41-generic", or sysinfo's Windows form
+/// "11 (22631)", which becomes "11.0.22631".
+pub fn normalize_os_version(os: &str, raw: &str) -> Option<String> {
+    if os == "windows"
+        && let Some((major, rest)) = raw.trim().split_once(" (")
+        && let Some(build) = rest.strip_suffix(')')
+        && major.chars().all(|c| c.is_ascii_digit())
+        && !build.is_empty()
+        && build.chars().all(|c| c.is_ascii_digit())
+    {
+        return Some(format!("{major}.0.{build}"));
+    }
+
+    extract_version(raw)
+}
+
+/// Find the version in free-form text such as `git version 2.43.0` or
+/// `v20.11.0`, preferring dotted numbers over bare ones.
+pub fn extract_version(raw: &str) -> Option<String> {
+    let candidates: Vec<String> = raw
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map(|token| token.trim_matches('.'))
+        .filter(|token| token.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|token| {
+            token
+                .split('.')
+                .take_while(|segment| !segment.is_empty())
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .collect();
+
+    candidates
+        .iter()
+        .find(|version| version.contains('.'))
+        .or_else(|| candidates.first())
+        .cloned()
+}
+
+/// Record the version of each named command that is on `PATH` and not
+/// already in `env.commands`. Missing commands are left out of the map.
+pub fn probe_commands<'a>(env: &mut Environment, names: impl IntoIterator<Item = &'a str>) {
+    for name in names {
+        if env.commands.contains_key(name) {
+            continue;
+        }
+        if let Some(version) = probe_command(name) {
+            env.commands.insert(name.to_string(), version);
+        }
+    }
+}
+
+fn probe_command(name: &str) -> Option<String> {
+    let path = which(name).ok()?;
+    let version = Command::new(path)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            extract_version(&String::from_utf8_lossy(&output.stdout))
+                .or_else(|| extract_version(&String::from_utf8_lossy(&output.stderr)))
+        });
+    Some(version.unwrap_or_else(|| "unknown".to_string()))
+}
+
+/// Free space, in GiB, on the disk whose mount point most specifically
+/// contains `path`, or its nearest existing ancestor when `path` has not been
+/// created yet.
+pub fn disk_free_gb_at(path: &Path) -> Option<u64> {
+    let path = path.ancestors().find_map(|dir| dir.canonicalize().ok())?;
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space() / 1_073_741_824)
+}
+
+/// Canonical spelling of a CPU feature name: lowercase with `_` for `.`,
+/// so `SSE4.2` and `sse4_2` are the same feature.
+pub fn normalize_cpu_feature(name: &str) -> String {
+    name.trim().to_ascii_lowercase().replace('.', "_")
+}
+
+#[allow(unused_mut)]
+fn detect_cpu_features() -> Vec<String> {
+    let mut features = Vec::new();
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        macro_rules! probe {
+            ($($feature:tt),* $(,)?) => {
+                $(
+                    if std::arch::is_x86_feature_detected!($feature) {
+                        features.push(normalize_cpu_feature($feature));
+                    }
+                )*
+            };
+        }
+        probe!(
+            "sse2", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "avx", "avx2", "fma", "bmi1",
+            "bmi2", "f16c", "aes", "sha", "avx512f",
+        );
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        macro_rules! probe {
+            ($($feature:tt),* $(,)?) => {
+                $(
+                    if std::arch::is_aarch64_feature_detected!($feature) {
+                        features.push(normalize_cpu_feature($feature));
+                    }
+                )*
+            };
+        }
+        probe!(
+            "neon", "aes", "sha2", "crc", "lse", "fp16", "dotprod", "sve"
+        );
+    }
+
+    features
+}
+
 fn normalize_os(raw: &str) -> String {
     match raw {
         "macos" => "macos".to_string(),
         "windows" => "windows".to_string(),
+        "linux" => "linux".to_string(),
         other => other.to_lowercase(),
     }
 }
@@ -101,10 +362,8 @@ fn detect_package_managers(os: &str) -> Vec<String> {
     let mut managers = Vec::new();
 
     match os {
-        "macos" => {
-            if has_command("brew") {
-                managers.push("brew".to_string());
-            }
+        "macos" if has_command("brew") => {
+            managers.push("brew".to_string());
         }
         "windows" => {
             if has_command("winget") {
@@ -117,12 +376,117 @@ fn detect_package_managers(os: &str) -> Vec<String> {
                 managers.push("scoop".to_string());
             }
         }
+        "linux" => {
+            for manager in ["apt", "dnf", "pacman", "zypper", "apk"] {
+                if has_command(manager) {
+                    managers.push(manager.to_string());
+                }
+            }
+        }
         _ => {}
     }
 
     managers
 }
 
+/// Identify the C library from `ldd --version`, falling back to the presence
+/// of the musl dynamic loader when `ldd` is missing.
+fn detect_libc() -> Option<(String, Option<String>)> {
+    // musl's ldd prints its banner to stderr and exits with status 1.
+    let from_ldd = Command::new("ldd")
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            parse_ldd_version(&text)
+        });
+
+    from_ldd.or_else(|| {
+        let has_musl_loader = std::fs::read_dir("/lib").ok()?.any(|entry| {
+            entry.is_ok_and(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"))
+        });
+        has_musl_loader.then(|| ("musl".to_string(), None))
+    })
+}
+
+/// Parse the libc family and version from `ldd --version` output, e.g.
+/// `ldd (Ubuntu GLIBC 2.35-0ubuntu3) 2.35` or musl's `Version 1.2.4` banner.
+pub fn parse_ldd_version(output: &str) -> Option<(String, Option<String>)> {
+    let lower = output.to_lowercase();
+    let family = if lower.contains("musl") {
+        "musl"
+    } else if lower.contains("glibc")
+        || lower.contains("gnu libc")
+        || lower.contains("gnu c library")
+    {
+        "glibc"
+    } else {
+        return None;
+    };
+    let first_line = output.lines().next().unwrap_or_default();
+    let version = match family {
+        "glibc" => extract_version(first_line),
+        _ => output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Version"))
+            .and_then(extract_version),
+    };
+    Some((family.to_string(), version))
+}
+
+fn read_os_release() -> OsRelease {
+    ["/etc/os-release", "/usr/lib/os-release"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .map(|contents| parse_os_release(&contents))
+        .unwrap_or_default()
+}
+
+/// Parse the `KEY=value` lines of an os-release file, unquoting values.
+pub fn parse_os_release(contents: &str) -> OsRelease {
+    let mut release = OsRelease::default();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, raw_value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = unquote(raw_value.trim());
+        if value.is_empty() {
+            continue;
+        }
+
+        match key.trim() {
+            "ID" => release.id = Some(value.to_lowercase()),
+            "VERSION_ID" => release.version_id = Some(value),
+            "ID_LIKE" => {
+                release.id_like = value
+                    .split_whitespace()
+                    .map(|id| id.to_lowercase())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    release
+}
+
+fn unquote(value: &str) -> String {
+    let stripped = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value);
+    stripped.replace("\\\"", "\"").replace("\\$", "$")
+}
+
 fn has_command(cmd: &str) -> bool {
     which(cmd).is_ok()
         || Command::new(cmd)
@@ -131,3 +495,183 @@ fn has_command(cmd: &str) -> bool {
             .map(|output| output.status.success())
             .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{
+        Environment, disk_free_gb_at, extract_version, normalize_os_version, parse_ldd_version,
+        parse_os_release,
+    };
+
+    #[test]
+    fn measures_disk_at_the_nearest_existing_ancestor() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("not/created/yet");
+        assert_eq!(disk_free_gb_at(&missing), disk_free_gb_at(dir.path()));
+    }
+
+    #[test]
+    fn parses_quoted_os_release_fields() {
+        let contents = r#"
+PRETTY_NAME="Ubuntu 22.04.4 LTS"
+NAME="Ubuntu"
+VERSION_ID="22.04"
+ID=ubuntu
+ID_LIKE=debian
+"#;
+        let release = parse_os_release(contents);
+        assert_eq!(release.id.as_deref(), Some("ubuntu"));
+        assert_eq!(release.version_id.as_deref(), Some("22.04"));
+        assert_eq!(release.id_like, vec!["debian".to_string()]);
+    }
+
+    #[test]
+    fn splits_multiple_id_like_entries() {
+        let release =
+            parse_os_release("ID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\nVERSION_ID='9.3'\n");
+        assert_eq!(release.id.as_deref(), Some("rocky"));
+        assert_eq!(release.version_id.as_deref(), Some("9.3"));
+        assert_eq!(release.id_like, vec!["rhel", "centos", "fedora"]);
+    }
+
+    #[test]
+    fn parses_glibc_and_musl_ldd_output() {
+        let glibc = "ldd (Ubuntu GLIBC 2.35-0ubuntu3.8) 2.35\nCopyright (C) 2022 Free Software Foundation, Inc.\n";
+        assert_eq!(
+            parse_ldd_version(glibc),
+            Some(("glibc".to_string(), Some("2.35".to_string())))
+        );
+        let musl = "musl libc (x86_64)\nVersion 1.2.4\nDynamic Program Loader\n";
+        assert_eq!(
+            parse_ldd_version(musl),
+            Some(("musl".to_string(), Some("1.2.4".to_string())))
+        );
+        assert_eq!(parse_ldd_version("ldd: command not found"), None);
+    }
+
+    #[test]
+    fn deserializes_hand_written_profiles() {
+        let env: Environment = serde_json::from_str(
+            r#"{ "os": "windows", "os_version": "10.0.19045", "cpu_arch": "x64", "ram_gb": 4 }"#,
+        )
+        .expect("minimal profile should parse");
+        assert_eq!(env.os, "windows");
+        assert!(env.pkg_managers.is_empty());
+        assert!(env.distro_id.is_none());
+        assert!(env.fingerprint.is_none());
+        assert!(env.commands.is_empty());
+        assert!(env.cpu_cores.is_none() && env.disk_free_gb.is_none());
+    }
+
+    #[test]
+    fn round_trips_saved_profiles() {
+        let env = Environment {
+            os: "linux".into(),
+            os_version: "6.5.0".into(),
+            os_version_display: "Linux 22.04 Ubuntu".into(),
+            cpu_arch: "x64".into(),
+            ram_gb: 16,
+            cpu_cores: Some(8),
+            cpu_features: vec!["sse4_2".into(), "avx2".into()],
+            disk_free_gb: Some(120),
+            disk_free_gb_by_mode: Default::default(),
+            pkg_managers: vec!["apt".into()],
+            commands: BTreeMap::from([("git".to_string(), "2.43.0".to_string())]),
+            distro_id: Some("ubuntu".into()),
+            distro_version: Some("22.04".into()),
+            id_like: vec!["debian".into()],
+            libc: Some("glibc".into()),
+            libc_version: Some("2.35".into()),
+            fingerprint: Some(super::compute_fingerprint(
+                "linux",
+                "Linux 22.04 Ubuntu",
+                "x64",
+                16,
+                Some("build-01".into()),
+            )),
+        };
+        let json = serde_json::to_string(&env).unwrap();
+        let restored: Environment = serde_json::from_str(&json).expect("round trip");
+        assert_eq!(restored.os, env.os);
+        assert_eq!(restored.id_like, env.id_like);
+        assert_eq!(restored.cpu_features, env.cpu_features);
+        assert_eq!(restored.commands, env.commands);
+        assert_eq!(restored.disk_free_gb, Some(120));
+        assert_eq!(restored.libc_version.as_deref(), Some("2.35"));
+        assert_eq!(
+            restored.fingerprint.map(|f| f.hash),
+            env.fingerprint.map(|f| f.hash)
+        );
+    }
+
+    #[test]
+    fn normalizes_display_versions() {
+        let cases = [
+            ("macos", "macOS 14.0 Sonoma", "14.0"),
+            ("macos", "13.6.1", "13.6.1"),
+            ("windows", "11 (22631)", "11.0.22631"),
+            ("windows", "10 (19045)", "10.0.19045"),
+            ("windows", "Windows 11 Pro", "11"),
+            ("linux", "6.5.0-41-generic", "6.5.0"),
+            ("linux", "Linux 12 Debian GNU/Linux", "12"),
+            ("linux", "5.15..3", "5.15"),
+        ];
+        for (os, raw, expected) in cases {
+            assert_eq!(
+                normalize_os_version(os, raw).as_deref(),
+                Some(expected),
+                "{raw}"
+            );
+        }
+        assert_eq!(normalize_os_version("linux", "unknown"), None);
+    }
+
+    #[test]
+    fn extracts_versions_from_version_output() {
+        let cases = [
+            ("git version 2.43.0\n", "2.43.0"),
+            ("v20.11.0", "20.11.0"),
+            ("Python 3.11.4", "3.11.4"),
+            ("Docker version 24.0.7, build afdd53b", "24.0.7"),
+            ("OpenJDK 64-Bit Server VM (build 21.0.1+12)", "21.0.1"),
+            ("jq-1", "1"),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(extract_version(raw).as_deref(), Some(expected), "{raw}");
+        }
+        assert_eq!(extract_version("no digits here"), None);
+    }
+
+    #[test]
+    fn probes_only_commands_on_path() {
+        let mut env: Environment = serde_json::from_str(
+            r#"{ "os": "linux", "os_version": "6.5", "cpu_arch": "x64", "ram_gb": 4 }"#,
+        )
+        .unwrap();
+        env.commands.insert("sh".into(), "preset".into());
+
+        super::probe_commands(&mut env, ["sh", "definitely-not-a-real-command-xyz"]);
+        assert_eq!(env.commands.get("sh").map(String::as_str), Some("preset"));
+        assert!(
+            !env.commands
+                .contains_key("definitely-not-a-real-command-xyz")
+        );
+    }
+
+    #[test]
+    fn loading_old_profiles_normalizes_the_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mac.json");
+        std::fs::write(
+            &path,
+            r#"{ "os": "macos", "os_version": "macOS 14.0 Sonoma", "cpu_arch": "arm64", "ram_gb": 16 }"#,
+        )
+        .unwrap();
+
+        let env = super::load_environment(&path).expect("profile loads");
+        assert_eq!(env.os_version, "14.0");
+        assert_eq!(env.os_version_display, "macOS 14.0 Sonoma");
+    }
+}
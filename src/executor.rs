@@ -6,25 +6,48 @@ use std::process::Command;
 use anyhow::{Context, anyhow};
 use serde::Serialize;
 
-use crate::manifest::{DownloadStep, ExtractStep, Step, TemplateConfigStep};
+use crate::download::{DownloadOptions, DownloadRecord, perform_download};
+use crate::manifest::{ExtractStep, Step, TemplateConfigStep};
 use crate::planner::{InstallPlan, PlannedStep};
 use crate::runtime_env::{ExecutionContext, prepare_runtime_env};
+use crate::vars::replace_placeholders;
 
 #[derive(Debug, Serialize)]
 pub struct ExecutionResult {
     pub completed_steps: usize,
     pub total_steps: usize,
+    /// The URL each download step fetched its file from.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub downloads: Vec<DownloadRecord>,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum ExecutionError {
+    /// `downloads` holds the files fetched before the step failed.
     #[error("step {index} failed: {message}")]
-    StepFailed { index: usize, message: String },
+    StepFailed {
+        index: usize,
+        message: String,
+        downloads: Vec<DownloadRecord>,
+    },
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+/// Settings for running a plan; the defaults suit interactive installs.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionOptions {
+    pub download: DownloadOptions,
+}
+
 pub fn execute_plan(plan: &InstallPlan) -> Result<ExecutionResult, ExecutionError> {
+    execute_plan_with_options(plan, &ExecutionOptions::default())
+}
+
+pub fn execute_plan_with_options(
+    plan: &InstallPlan,
+    options: &ExecutionOptions,
+) -> Result<ExecutionResult, ExecutionError> {
     println!(
         "Executing plan for {} {} using '{}' mode ({} steps)",
         plan.app_name,
@@ -34,6 +57,7 @@ pub fn execute_plan(plan: &InstallPlan) -> Result<ExecutionResult, ExecutionErro
     );
 
     let context = prepare_runtime_env(plan).map_err(ExecutionError::Other)?;
+    let mut downloads = Vec::new();
 
     for (idx, step) in plan.steps.iter().enumerate() {
         println!(
@@ -42,13 +66,13 @@ pub fn execute_plan(plan: &InstallPlan) -> Result<ExecutionResult, ExecutionErro
             plan.steps.len(),
             step.description
         );
-        if let Err(err) = execute_step(&plan.os, step, context.as_ref()) {
-            return Err(match err {
-                ExecutionError::StepFailed { .. } => err,
-                ExecutionError::Other(source) => ExecutionError::StepFailed {
-                    index: idx,
-                    message: source.to_string(),
-                },
+        if let Err(message) =
+            execute_step(&plan.os, step, context.as_ref(), options, &mut downloads)
+        {
+            return Err(ExecutionError::StepFailed {
+                index: step.index,
+                message,
+                downloads,
             });
         }
     }
@@ -56,6 +80,7 @@ pub fn execute_plan(plan: &InstallPlan) -> Result<ExecutionResult, ExecutionErro
     Ok(ExecutionResult {
         completed_steps: plan.steps.len(),
         total_steps: plan.steps.len(),
+        downloads,
     })
 }
 
@@ -63,29 +88,26 @@ fn execute_step(
     os: &str,
     step: &PlannedStep,
     ctx: Option<&ExecutionContext>,
-) -> Result<(), ExecutionError> {
+    options: &ExecutionOptions,
+    downloads: &mut Vec<DownloadRecord>,
+) -> Result<(), String> {
     match &step.step {
-        Step::Run { run } => run_command(os, run, ctx).map_err(|err| ExecutionError::StepFailed {
-            index: step.index,
-            message: err.to_string(),
-        }),
+        Step::Run { run } => run_command(os, run, ctx).map_err(|err| err.to_string()),
         Step::Download { download } => {
-            perform_download(download).map_err(|err| ExecutionError::StepFailed {
-                index: step.index,
-                message: err.to_string(),
-            })
-        }
-        Step::Extract { extract } => {
-            perform_extract(extract).map_err(|err| ExecutionError::StepFailed {
-                index: step.index,
-                message: err.to_string(),
-            })
+            // Keep the whole context chain so checksum mismatches show both digests.
+            let source =
+                perform_download(download, &options.download).map_err(|err| format!("{err:#}"))?;
+            downloads.extend(source.map(|source| DownloadRecord {
+                step_index: step.index,
+                url: source.url,
+                dest: download.dest.clone(),
+                cached: source.cached,
+            }));
+            Ok(())
         }
+        Step::Extract { extract } => perform_extract(extract).map_err(|err| err.to_string()),
         Step::TemplateConfig { template_config } => {
-            render_template(template_config).map_err(|err| ExecutionError::StepFailed {
-                index: step.index,
-                message: err.to_string(),
-            })
+            render_template(template_config).map_err(|err| err.to_string())
         }
     }
 }
@@ -117,27 +139,6 @@ fn run_command(os: &str, command: &str, ctx: Option<&ExecutionContext>) -> anyho
     Ok(())
 }
 
-fn perform_download(step: &DownloadStep) -> anyhow::Result<()> {
-    let response =
-        reqwest::blocking::get(&step.url).with_context(|| format!("requesting {}", step.url))?;
-
-    if !response.status().is_success() {
-        return Err(anyhow!("download failed with status {}", response.status()));
-    }
-
-    if let Some(parent) = step.dest.parent() {
-        fs::create_dir_all(parent)?;
-    }
-
-    let mut dest_file = File::create(&step.dest)
-        .with_context(|| format!("creating destination file {}", step.dest.display()))?;
-    let bytes = response
-        .bytes()
-        .with_context(|| format!("reading response from {}", step.url))?;
-    dest_file.write_all(&bytes)?;
-    Ok(())
-}
-
 fn perform_extract(step: &ExtractStep) -> anyhow::Result<()> {
     let archive_path = &step.archive;
     let dest_dir = &step.dest;
@@ -179,7 +180,7 @@ fn perform_extract(step: &ExtractStep) -> anyhow::Result<()> {
 }
 
 fn sanitize_extract_path(dest: &PathBuf, name: &str) -> anyhow::Result<PathBuf> {
-    let mut path = PathBuf::from(name);
+    let path = PathBuf::from(name);
     if path
         .components()
         .any(|c| matches!(c, std::path::Component::ParentDir))
@@ -207,7 +208,7 @@ fn render_template(step: &TemplateConfigStep) -> anyhow::Result<()> {
         .with_context(|| format!("opening template {}", step.source.display()))?
         .read_to_string(&mut source)?;
 
-    let rendered = replace_placeholders(&source, &step.vars);
+    let rendered = replace_placeholders(&source, |key| step.vars.get(key).map(String::as_str));
 
     if let Some(parent) = step.dest.parent() {
         fs::create_dir_all(parent)?;
@@ -218,30 +219,3 @@ fn render_template(step: &TemplateConfigStep) -> anyhow::Result<()> {
     dest_file.write_all(rendered.as_bytes())?;
     Ok(())
 }
-
-fn replace_placeholders(
-    template: &str,
-    vars: &std::collections::HashMap<String, String>,
-) -> String {
-    let mut result = String::new();
-    let mut remainder = template;
-    while let Some(start) = remainder.find("{{") {
-        if let Some(end) = remainder[start + 2..].find("}}") {
-            let end_index = start + 2 + end;
-            result.push_str(&remainder[..start]);
-            let key = &remainder[start + 2..end_index];
-            if let Some(value) = vars.get(key.trim()) {
-                result.push_str(value);
-            } else {
-                result.push_str("{{");
-                result.push_str(key);
-                result.push_str("}}");
-            }
-            remainder = &remainder[end_index + 2..];
-            continue;
-        }
-        break;
-    }
-    result.push_str(remainder);
-    result
-}
@@ -1,10 +1,16 @@
+pub mod cache;
 pub mod cli;
+pub mod download;
 pub mod env_detect;
 pub mod executor;
 pub mod manifest;
+pub mod matrix;
+pub mod mirrors;
 pub mod planner;
+pub mod progress;
 pub mod runtime_env;
 pub mod state;
+pub mod vars;
 
 /// Run the command line interface and return an exit code.
 pub fn run_cli() -> i32 {
@@ -1,39 +1,268 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use anyhow::Context;
+use schemars::JsonSchema;
+use schemars::r#gen::SchemaGenerator;
+use schemars::schema::{
+    InstanceType, Metadata, ObjectValidation, RootSchema, Schema, SchemaObject, StringValidation,
+};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Manifest {
     pub name: String,
     pub version: String,
+    /// Variables interpolated as `{{name}}` into step fields.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    /// Mode names from most to least preferred; takes precedence over `priority`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mode_order: Vec<String>,
+    #[serde(default)]
     pub modes: BTreeMap<String, Mode>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Manifest {
+    /// Names of every command listed in any mode's `requirements.commands`
+    /// or `recommended.commands`, including nested `all`/`any`/`not` sets.
+    pub fn required_commands(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        for requirements in self
+            .modes
+            .values()
+            .flat_map(|mode| mode.requirements.iter().chain(&mode.recommended))
+        {
+            requirements.visit(&mut |node| {
+                names.extend(node.commands.iter().map(|command| command.name.as_str()));
+            });
+        }
+        names
+    }
+}
+
+impl Requirements {
+    /// Call `visit` on this set and every nested `all`/`any`/`not` set.
+    pub fn visit<'a>(&'a self, visit: &mut impl FnMut(&'a Requirements)) {
+        visit(self);
+        for child in self.all.iter().chain(&self.any) {
+            child.visit(visit);
+        }
+        if let Some(child) = &self.not {
+            child.visit(visit);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Mode {
+    /// Name of a mode to inherit requirements, recommendations, runtime_env,
+    /// vars and steps from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Ranking among compatible modes; higher wins. Not inherited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i64>,
     pub requirements: Option<Requirements>,
+    /// Soft requirements: when unmet the mode stays compatible but ranks
+    /// lower and its plan carries warnings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recommended: Option<Requirements>,
+    /// Per-platform step lists; with `extends`, each listed platform replaces
+    /// the inherited list.
+    #[serde(default)]
+    #[schemars(schema_with = "platform_steps_schema")]
     pub steps: BTreeMap<String, Vec<Step>>,
+    /// Steps inserted before the (inherited) platform list.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(schema_with = "platform_steps_schema")]
+    pub prepend_steps: BTreeMap<String, Vec<Step>>,
+    /// Steps added after the (inherited) platform list.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(schema_with = "platform_steps_schema")]
+    pub append_steps: BTreeMap<String, Vec<Step>>,
     #[serde(default)]
     pub runtime_env: Option<RuntimeEnv>,
+    /// Per-mode variables overriding the manifest-level ones.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Requirements {
     #[serde(default)]
     pub os: Vec<OsConstraint>,
     #[serde(default)]
     pub cpu_arch: Vec<String>,
     pub ram_gb: Option<u64>,
+    /// Free space, in GiB, on the filesystem holding the install root.
+    pub disk_free_gb: Option<u64>,
+    /// Minimum number of logical CPU cores.
+    pub cpu_cores: Option<u64>,
+    /// CPU features that must all be present, e.g. `avx2`, `sse4_2`, `neon`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu_features: Vec<String>,
+    /// Commands that must all be on `PATH`, optionally with a version range
+    /// checked against their `--version` output, e.g. `git>=2.30`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<CommandRequirement>,
+    /// Package managers of which at least one must be available.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pkg_managers: Vec<String>,
+    /// C library constraints of which at least one must match, e.g.
+    /// `glibc>=2.31` or `musl`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub libc: Vec<LibcConstraint>,
+    /// Nested requirement sets that must all be met.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub all: Vec<Requirements>,
+    /// Nested requirement sets of which at least one must be met.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub any: Vec<Requirements>,
+    /// A nested requirement set that must not be met.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not: Option<Box<Requirements>>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// An entry of `requirements.os`: a platform or distribution id plus zero or
+/// more version comparators that must all hold, e.g. `macos>=12,<15`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct OsConstraint {
     pub family: String,
-    pub min_version: Option<String>,
+    pub comparators: Vec<VersionComparator>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionComparator {
+    pub op: VersionOp,
+    /// Dotted version; `==` and `!=` also accept a trailing `.*` wildcard.
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionOp {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl VersionOp {
+    /// Operators in match order, longest first so `<=` is not read as `<`.
+    const ALL: [(&'static str, VersionOp); 6] = [
+        ("<=", VersionOp::Le),
+        (">=", VersionOp::Ge),
+        ("==", VersionOp::Eq),
+        ("!=", VersionOp::Ne),
+        ("<", VersionOp::Lt),
+        (">", VersionOp::Gt),
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VersionOp::Lt => "<",
+            VersionOp::Le => "<=",
+            VersionOp::Eq => "==",
+            VersionOp::Ne => "!=",
+            VersionOp::Ge => ">=",
+            VersionOp::Gt => ">",
+        }
+    }
+}
+
+impl VersionComparator {
+    /// The version prefix of a `10.*` style wildcard, if this is one.
+    pub fn wildcard_prefix(&self) -> Option<&str> {
+        if self.version == "*" {
+            Some("")
+        } else {
+            self.version.strip_suffix(".*")
+        }
+    }
+}
+
+/// Regex tail matching the optional comma-separated comparators.
+macro_rules! version_ranges_pattern {
+    () => {
+        concat!(
+            r"((<=|>=|==|!=|<|>)\s*[^\s,<>=!]+\s*",
+            r"(,\s*(<=|>=|==|!=|<|>)\s*[^\s,<>=!]+\s*)*)?$"
+        )
+    };
+}
+
+/// Regex mirroring the syntax accepted by [`parse_os_constraint`].
+pub const OS_CONSTRAINT_PATTERN: &str =
+    concat!(r"^\s*[A-Za-z0-9._-]+\s*", version_ranges_pattern!());
+
+/// Regex mirroring the syntax accepted by [`parse_command_requirement`].
+pub const COMMAND_REQUIREMENT_PATTERN: &str =
+    concat!(r"^\s*[A-Za-z0-9._+-]+\s*", version_ranges_pattern!());
+
+/// Regex mirroring the syntax accepted by [`parse_libc_constraint`].
+pub const LIBC_CONSTRAINT_PATTERN: &str =
+    concat!(r"^\s*(glibc|musl)\s*", version_ranges_pattern!());
+
+/// C library families `requirements.libc` can name.
+pub const LIBC_FAMILIES: [&str; 2] = ["glibc", "musl"];
+
+/// An entry of `requirements.libc`: a C library family plus optional version
+/// comparators, e.g. `glibc>=2.31`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibcConstraint {
+    pub family: String,
+    pub comparators: Vec<VersionComparator>,
+}
+
+/// An entry of `requirements.commands`: a program name plus optional version
+/// comparators, e.g. `git>=2.30` or `node>=18,<21`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandRequirement {
+    pub name: String,
+    pub comparators: Vec<VersionComparator>,
+}
+
+impl JsonSchema for OsConstraint {
+    fn schema_name() -> String {
+        "OsConstraint".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(OS_CONSTRAINT_PATTERN.to_string()),
+                ..Default::default()
+            })),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Platform (windows, macos, linux) or distribution id, optionally followed by \
+                     comma-separated version comparators (<, <=, ==, !=, >=, >); == and != \
+                     accept wildcards such as 10.*."
+                        .to_string(),
+                ),
+                examples: vec![
+                    "windows>=10".into(),
+                    "macos>=12,<15".into(),
+                    "ubuntu>=22.04".into(),
+                    "windows==10.*".into(),
+                ],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
 }
 
 impl<'de> Deserialize<'de> for OsConstraint {
@@ -46,28 +275,197 @@ impl<'de> Deserialize<'de> for OsConstraint {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl Serialize for OsConstraint {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for OsConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.family)?;
+        write_comparators(f, &self.comparators)
+    }
+}
+
+impl fmt::Display for CommandRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        write_comparators(f, &self.comparators)
+    }
+}
+
+fn write_comparators(f: &mut fmt::Formatter<'_>, comparators: &[VersionComparator]) -> fmt::Result {
+    for (idx, comparator) in comparators.iter().enumerate() {
+        if idx > 0 {
+            f.write_str(",")?;
+        }
+        write!(f, "{comparator}")?;
+    }
+    Ok(())
+}
+
+impl JsonSchema for CommandRequirement {
+    fn schema_name() -> String {
+        "CommandRequirement".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(COMMAND_REQUIREMENT_PATTERN.to_string()),
+                ..Default::default()
+            })),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Command that must be on PATH, optionally followed by version comparators \
+                     checked against its --version output."
+                        .to_string(),
+                ),
+                examples: vec!["git>=2.30".into(), "node>=18,<21".into(), "brew".into()],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandRequirement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        parse_command_requirement(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for CommandRequirement {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for LibcConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.family)?;
+        write_comparators(f, &self.comparators)
+    }
+}
+
+impl JsonSchema for LibcConstraint {
+    fn schema_name() -> String {
+        "LibcConstraint".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(LIBC_CONSTRAINT_PATTERN.to_string()),
+                ..Default::default()
+            })),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "C library family (glibc or musl), optionally followed by version \
+                     comparators."
+                        .to_string(),
+                ),
+                examples: vec!["glibc>=2.31".into(), "musl".into()],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl<'de> Deserialize<'de> for LibcConstraint {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        parse_libc_constraint(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for LibcConstraint {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for VersionComparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.op.as_str(), self.version)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(untagged)]
+#[serde(deny_unknown_fields)]
 pub enum Step {
+    /// Run a command in the platform shell.
     Run { run: String },
+    /// Download a URL to a local path.
     Download { download: DownloadStep },
+    /// Extract a zip archive into a directory.
     Extract { extract: ExtractStep },
+    /// Render a template file with `{{VAR}}` placeholders.
     TemplateConfig { template_config: TemplateConfigStep },
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct DownloadStep {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub url: String,
+    /// Further URLs for the same file, tried in order after `url`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
     pub dest: PathBuf,
+    /// Expected SHA-256 of the file, as hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Expected SHA-512 of the file, as hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    /// Expected size of the file in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl DownloadStep {
+    /// `url` followed by `urls`, skipping an unset `url`.
+    pub fn sources(&self) -> Vec<&str> {
+        std::iter::once(self.url.as_str())
+            .filter(|url| !url.is_empty())
+            .chain(self.urls.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ExtractStep {
     pub archive: PathBuf,
     pub dest: PathBuf,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct TemplateConfigStep {
     pub source: PathBuf,
     pub dest: PathBuf,
@@ -75,11 +473,39 @@ pub struct TemplateConfigStep {
     pub vars: HashMap<String, String>,
 }
 
+/// Step lists are keyed by platform, so only the supported platform names are
+/// allowed as properties.
+fn platform_steps_schema(generator: &mut SchemaGenerator) -> Schema {
+    let steps = generator.subschema_for::<Vec<Step>>();
+    let mut object = ObjectValidation {
+        additional_properties: Some(Box::new(Schema::Bool(false))),
+        ..Default::default()
+    };
+    for platform in SUPPORTED_PLATFORMS {
+        object
+            .properties
+            .insert(platform.to_string(), steps.clone());
+    }
+
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(object)),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// JSON Schema describing the manifest format accepted by [`load_manifest`].
+pub fn manifest_schema() -> RootSchema {
+    schemars::schema_for!(Manifest)
+}
+
 fn default_runtime_root() -> PathBuf {
     PathBuf::from(".enzyme_env")
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct RuntimeEnv {
     #[serde(rename = "type")]
     pub kind: RuntimeEnvType,
@@ -91,21 +517,23 @@ pub struct RuntimeEnv {
     pub python: Option<PythonRuntime>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum RuntimeEnvType {
     NodeLocal,
     PythonVenv,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct NodeRuntime {
     pub version: Option<String>,
     #[serde(default)]
     pub install_strategy: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct PythonRuntime {
     pub version: Option<String>,
     #[serde(default)]
@@ -113,46 +541,90 @@ pub struct PythonRuntime {
 }
 
 impl Step {
-    pub fn validate(&self) -> Result<(), ManifestValidationError> {
+    /// Check step fields, returning each problem with its JSON pointer
+    /// relative to the step (for example `download/url`).
+    pub fn validate(&self) -> Vec<(&'static str, String)> {
+        let mut problems = Vec::new();
         match self {
-            Step::Run { run } if run.trim().is_empty() => Err(
-                ManifestValidationError::InvalidStep("run command cannot be empty".to_string()),
-            ),
-            Step::Download { download } if download.url.trim().is_empty() => Err(
-                ManifestValidationError::InvalidStep("download url cannot be empty".to_string()),
-            ),
-            Step::Download { download } if download.dest.as_os_str().is_empty() => Err(
-                ManifestValidationError::InvalidStep("download dest cannot be empty".to_string()),
-            ),
-            Step::Extract { extract } if extract.archive.as_os_str().is_empty() => Err(
-                ManifestValidationError::InvalidStep("extract archive cannot be empty".to_string()),
-            ),
-            Step::Extract { extract } if extract.dest.as_os_str().is_empty() => Err(
-                ManifestValidationError::InvalidStep("extract dest cannot be empty".to_string()),
-            ),
-            Step::TemplateConfig { template_config }
-                if template_config.source.as_os_str().is_empty() =>
-            {
-                Err(ManifestValidationError::InvalidStep(
-                    "template_config source cannot be empty".to_string(),
-                ))
+            Step::Run { run } => {
+                if run.trim().is_empty() {
+                    problems.push(("run", "run command cannot be empty".to_string()));
+                }
+            }
+            Step::Download { download } => {
+                if download.url.trim().is_empty() && download.urls.is_empty() {
+                    problems.push(("download/url", "download url cannot be empty".to_string()));
+                }
+                if download.urls.iter().any(|url| url.trim().is_empty()) {
+                    problems.push((
+                        "download/urls",
+                        "download urls cannot contain an empty entry".to_string(),
+                    ));
+                }
+                if download.dest.as_os_str().is_empty() {
+                    problems.push(("download/dest", "download dest cannot be empty".to_string()));
+                }
+                for (field, digest, len) in [
+                    ("download/sha256", &download.sha256, 64),
+                    ("download/sha512", &download.sha512, 128),
+                ] {
+                    if let Some(digest) = digest
+                        && (digest.len() != len || !digest.chars().all(|c| c.is_ascii_hexdigit()))
+                    {
+                        problems.push((
+                            field,
+                            format!(
+                                "{} must be {len} hex digits",
+                                field.trim_start_matches("download/")
+                            ),
+                        ));
+                    }
+                }
+            }
+            Step::Extract { extract } => {
+                if extract.archive.as_os_str().is_empty() {
+                    problems.push((
+                        "extract/archive",
+                        "extract archive cannot be empty".to_string(),
+                    ));
+                }
+                if extract.dest.as_os_str().is_empty() {
+                    problems.push(("extract/dest", "extract dest cannot be empty".to_string()));
+                }
             }
-            Step::TemplateConfig { template_config }
-                if template_config.dest.as_os_str().is_empty() =>
-            {
-                Err(ManifestValidationError::InvalidStep(
-                    "template_config dest cannot be empty".to_string(),
-                ))
+            Step::TemplateConfig { template_config } => {
+                if template_config.source.as_os_str().is_empty() {
+                    problems.push((
+                        "template_config/source",
+                        "template_config source cannot be empty".to_string(),
+                    ));
+                }
+                if template_config.dest.as_os_str().is_empty() {
+                    problems.push((
+                        "template_config/dest",
+                        "template_config dest cannot be empty".to_string(),
+                    ));
+                }
             }
-            _ => Ok(()),
         }
+        problems
     }
 
     pub fn description(&self) -> String {
         match self {
             Step::Run { run } => format!("Run: {run}"),
             Step::Download { download } => {
-                format!("Download {} to {}", download.url, download.dest.display())
+                let sources = download.sources();
+                let fallbacks = match sources.len() {
+                    0 | 1 => String::new(),
+                    2 => " (1 fallback URL)".to_string(),
+                    n => format!(" ({} fallback URLs)", n - 1),
+                };
+                format!(
+                    "Download {} to {}{fallbacks}",
+                    sources.first().copied().unwrap_or_default(),
+                    download.dest.display()
+                )
             }
             Step::Extract { extract } => {
                 format!(
@@ -179,8 +651,77 @@ impl Step {
 
 #[derive(Debug, Error)]
 pub enum ManifestError {
-    #[error("invalid OS constraint format: {0}")]
-    InvalidOsConstraint(String),
+    #[error("invalid OS constraint '{constraint}': {reason}")]
+    InvalidOsConstraint { constraint: String, reason: String },
+    #[error("invalid command requirement '{requirement}': {reason}")]
+    InvalidCommandRequirement { requirement: String, reason: String },
+    #[error("invalid libc constraint '{constraint}': {reason}")]
+    InvalidLibcConstraint { constraint: String, reason: String },
+    #[error("unknown manifest format '{0}' (expected json, yaml, or toml)")]
+    UnknownFormat(String),
+    #[error("{format} parse error{}: {message}", format_location(*.line, *.column))]
+    Parse {
+        format: ManifestFormat,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+}
+
+fn format_location(line: Option<usize>, column: Option<usize>) -> String {
+    match (line, column) {
+        (Some(line), Some(column)) => format!(" at line {line}, column {column}"),
+        (Some(line), None) => format!(" at line {line}"),
+        _ => String::new(),
+    }
+}
+
+/// Serialization formats accepted for manifest files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ManifestFormat {
+    /// Infer the format from a file extension, defaulting to JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("yaml") | Some("yml") => ManifestFormat::Yaml,
+            Some("toml") => ManifestFormat::Toml,
+            _ => ManifestFormat::Json,
+        }
+    }
+}
+
+impl FromStr for ManifestFormat {
+    type Err = ManifestError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(ManifestFormat::Json),
+            "yaml" | "yml" => Ok(ManifestFormat::Yaml),
+            "toml" => Ok(ManifestFormat::Toml),
+            other => Err(ManifestError::UnknownFormat(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ManifestFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ManifestFormat::Json => "JSON",
+            ManifestFormat::Yaml => "YAML",
+            ManifestFormat::Toml => "TOML",
+        };
+        f.write_str(name)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -199,146 +740,904 @@ pub enum ManifestValidationError {
     InvalidRequirement(String, String),
     #[error("mode '{0}' has an invalid step: {1}")]
     InvalidStep(String, String),
+    #[error("mode '{mode}' extends unknown mode '{parent}'")]
+    UnknownParentMode { mode: String, parent: String },
+    #[error("mode inheritance cycle: {}", .0.join(" -> "))]
+    InheritanceCycle(Vec<String>),
+    #[error("mode_order names unknown mode '{0}'")]
+    UnknownOrderedMode(String),
+    #[error("mode_order lists mode '{0}' more than once")]
+    DuplicateOrderedMode(String),
+    #[error("invalid variable name '{0}'")]
+    InvalidVariable(String),
+    #[error("unknown field '{field}'{}", suggestion_hint(.suggestion))]
+    UnknownField {
+        field: String,
+        suggestion: Option<String>,
+    },
+    #[error("'{family}' is not a known platform or distribution id{}", suggestion_hint(.suggestion))]
+    UnknownOsFamily {
+        family: String,
+        suggestion: Option<String>,
+    },
 }
 
 pub fn parse_os_constraint(raw: &str) -> Result<OsConstraint, ManifestError> {
-    if let Some((family_part, version_part)) = raw.split_once(">=") {
-        let family = family_part.trim().to_lowercase();
-        let min_version = Some(version_part.trim().to_string());
-        if family.is_empty() || version_part.trim().is_empty() {
-            return Err(ManifestError::InvalidOsConstraint(raw.to_string()));
-        }
+    let invalid = |reason: String| ManifestError::InvalidOsConstraint {
+        constraint: raw.to_string(),
+        reason,
+    };
 
-        return Ok(OsConstraint {
-            family,
-            min_version,
-        });
+    let (family, comparators) = split_version_constraint(raw).map_err(invalid)?;
+    let family = family.to_lowercase();
+    if family.is_empty() {
+        return Err(invalid("missing OS family".to_string()));
+    }
+
+    Ok(OsConstraint {
+        family,
+        comparators,
+    })
+}
+
+pub fn parse_command_requirement(raw: &str) -> Result<CommandRequirement, ManifestError> {
+    let invalid = |reason: String| ManifestError::InvalidCommandRequirement {
+        requirement: raw.to_string(),
+        reason,
+    };
+
+    let (name, comparators) = split_version_constraint(raw).map_err(invalid)?;
+    if name.is_empty() {
+        return Err(invalid("missing command name".to_string()));
     }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-'))
+    {
+        return Err(invalid(format!("'{name}' is not a command name")));
+    }
+
+    Ok(CommandRequirement {
+        name: name.to_string(),
+        comparators,
+    })
+}
+
+pub fn parse_libc_constraint(raw: &str) -> Result<LibcConstraint, ManifestError> {
+    let invalid = |reason: String| ManifestError::InvalidLibcConstraint {
+        constraint: raw.to_string(),
+        reason,
+    };
+
+    let (family, comparators) = split_version_constraint(raw).map_err(invalid)?;
+    let family = family.to_lowercase();
+    if family.is_empty() {
+        return Err(invalid("missing libc family".to_string()));
+    }
+    if !LIBC_FAMILIES.contains(&family.as_str()) {
+        return Err(invalid(format!(
+            "unknown libc family '{family}' (expected {})",
+            LIBC_FAMILIES.join(" or ")
+        )));
+    }
+
+    Ok(LibcConstraint {
+        family,
+        comparators,
+    })
+}
+
+/// Split `name>=1.2,<2` into its trimmed name and comparators.
+fn split_version_constraint(raw: &str) -> Result<(&str, Vec<VersionComparator>), String> {
+    let (name, ranges) = match raw.find(['<', '>', '=', '!']) {
+        Some(idx) => (&raw[..idx], Some(&raw[idx..])),
+        None => (raw, None),
+    };
+
+    let mut comparators = Vec::new();
+    for part in ranges.into_iter().flat_map(|ranges| ranges.split(',')) {
+        let part = part.trim();
+        let (op, version) = VersionOp::ALL
+            .iter()
+            .find_map(|(symbol, op)| part.strip_prefix(symbol).map(|rest| (*op, rest.trim())))
+            .ok_or_else(|| format!("'{part}' does not start with <, <=, ==, !=, >=, or >"))?;
+
+        if version.is_empty() {
+            return Err(format!("'{part}' is missing a version"));
+        }
+        if version.contains(|c: char| c.is_whitespace() || "<>=!,".contains(c)) {
+            return Err(format!("'{version}' is not a version"));
+        }
+        let is_wildcard = version == "*" || version.ends_with(".*");
+        if version.contains('*') && !is_wildcard {
+            return Err("wildcards are only allowed as a final '.*' segment".to_string());
+        }
+        if is_wildcard && !matches!(op, VersionOp::Eq | VersionOp::Ne) {
+            return Err("wildcards are only allowed with == and !=".to_string());
+        }
 
-    if !raw.trim().is_empty() {
-        return Ok(OsConstraint {
-            family: raw.trim().to_lowercase(),
-            min_version: None,
+        comparators.push(VersionComparator {
+            op,
+            version: version.to_string(),
         });
     }
 
-    Err(ManifestError::InvalidOsConstraint(raw.to_string()))
+    Ok((name.trim(), comparators))
 }
 
 pub fn load_manifest(path: &Path) -> anyhow::Result<Manifest> {
+    load_manifest_with_format(path, None)
+}
+
+/// Load a manifest, using `format` when given and the file extension otherwise.
+pub fn load_manifest_with_format(
+    path: &Path,
+    format: Option<ManifestFormat>,
+) -> anyhow::Result<Manifest> {
     let data = fs::read_to_string(path)
         .with_context(|| format!("reading manifest at {}", path.display()))?;
-    let manifest: Manifest = serde_json::from_str(&data)
+    let format = format.unwrap_or_else(|| ManifestFormat::from_path(path));
+    let document: serde_json::Value = parse_document(&data, format)
+        .with_context(|| format!("parsing manifest at {}", path.display()))?;
+    if let Err(unknown) = check_unknown_fields(&document) {
+        let errors = revalidate_without_unknown_fields(document, unknown);
+        return Err(errors).with_context(|| format!("validating manifest at {}", path.display()));
+    }
+    let manifest = parse_manifest(&data, format)
         .with_context(|| format!("parsing manifest at {}", path.display()))?;
     validate_manifest(manifest)
         .with_context(|| format!("validating manifest at {}", path.display()))
 }
 
-fn validate_manifest(manifest: Manifest) -> Result<Manifest, ManifestValidationError> {
+/// Drop the unknown keys and run the regular validation on what remains, so a
+/// typo does not hide the manifest's other problems.
+fn revalidate_without_unknown_fields(
+    mut document: serde_json::Value,
+    mut errors: ManifestValidationErrors,
+) -> ManifestValidationErrors {
+    for issue in &errors.issues {
+        let Some((parent, key)) = issue.path.rsplit_once('/') else {
+            continue;
+        };
+        let key = key.replace("~1", "/").replace("~0", "~");
+        if let Some(object) = document.pointer_mut(parent).and_then(|v| v.as_object_mut()) {
+            object.remove(&key);
+        }
+    }
+
+    if let Ok(manifest) = serde_json::from_value::<Manifest>(document)
+        && let Err(remaining) = validate_manifest(manifest)
+    {
+        errors.issues.extend(remaining.issues);
+    }
+    errors
+}
+
+pub fn parse_manifest(data: &str, format: ManifestFormat) -> Result<Manifest, ManifestError> {
+    parse_document(data, format)
+}
+
+fn parse_document<T: DeserializeOwned>(
+    data: &str,
+    format: ManifestFormat,
+) -> Result<T, ManifestError> {
+    match format {
+        ManifestFormat::Json => serde_json::from_str(data).map_err(|err| {
+            let (line, column) = (err.line(), err.column());
+            ManifestError::Parse {
+                format,
+                line: (line > 0).then_some(line),
+                column: (column > 0).then_some(column),
+                message: strip_location(&err.to_string(), line, column),
+            }
+        }),
+        ManifestFormat::Yaml => serde_yaml::from_str(data).map_err(|err| {
+            let location = err.location();
+            let line = location.as_ref().map(|loc| loc.line());
+            let column = location.as_ref().map(|loc| loc.column());
+            ManifestError::Parse {
+                format,
+                line,
+                column,
+                message: strip_location(&err.to_string(), line.unwrap_or(0), column.unwrap_or(0)),
+            }
+        }),
+        ManifestFormat::Toml => toml::from_str(data).map_err(|err| {
+            let (line, column) = err
+                .span()
+                .map(|span| line_and_column(data, span.start))
+                .unzip();
+            ManifestError::Parse {
+                format,
+                line,
+                column,
+                message: err.message().trim().to_string(),
+            }
+        }),
+    }
+}
+
+/// serde_json and serde_yaml embed `at line L column C` in their messages;
+/// drop it since the location is reported separately.
+fn strip_location(message: &str, line: usize, column: usize) -> String {
+    let location = format!(" at line {line} column {column}");
+    message.replacen(&location, "", 1).trim().to_string()
+}
+
+fn line_and_column(data: &str, offset: usize) -> (usize, usize) {
+    let prefix = &data[..offset.min(data.len())];
+    let line = prefix.matches('\n').count() + 1;
+    let column = prefix
+        .rsplit('\n')
+        .next()
+        .map(|last| last.chars().count() + 1)
+        .unwrap_or(1);
+    (line, column)
+}
+
+/// A single validation problem, located by a JSON pointer into the manifest.
+#[derive(Debug, Error)]
+#[error("{path}: {error}")]
+pub struct ValidationIssue {
+    pub path: String,
+    pub error: ManifestValidationError,
+}
+
+impl Serialize for ValidationIssue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("ValidationIssue", 2)?;
+        state.serialize_field("path", &self.path)?;
+        state.serialize_field("message", &self.error.to_string())?;
+        state.end()
+    }
+}
+
+/// Every problem found while validating a manifest.
+#[derive(Debug, Error)]
+#[error("{} validation error(s): {}", .issues.len(), join_issues(.issues))]
+pub struct ManifestValidationErrors {
+    pub issues: Vec<ValidationIssue>,
+}
+
+fn join_issues(issues: &[ValidationIssue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Append a segment to a JSON pointer, escaping `~` and `/` per RFC 6901.
+fn pointer(base: &str, segment: impl fmt::Display) -> String {
+    let segment = segment.to_string().replace('~', "~0").replace('/', "~1");
+    format!("{base}/{segment}")
+}
+
+#[derive(Default)]
+struct IssueCollector {
+    issues: Vec<ValidationIssue>,
+}
+
+impl IssueCollector {
+    fn push(&mut self, path: String, error: ManifestValidationError) {
+        self.issues.push(ValidationIssue { path, error });
+    }
+
+    fn check(&mut self, path: String, result: Result<(), ManifestValidationError>) {
+        if let Err(error) = result {
+            self.push(path, error);
+        }
+    }
+}
+
+fn validate_manifest(mut manifest: Manifest) -> Result<Manifest, ManifestValidationErrors> {
+    let mut issues = IssueCollector::default();
+
     if manifest.name.trim().is_empty() {
-        return Err(ManifestValidationError::MissingField("name".to_string()));
+        issues.push(
+            "/name".to_string(),
+            ManifestValidationError::MissingField("name".to_string()),
+        );
     }
 
     if manifest.version.trim().is_empty() {
-        return Err(ManifestValidationError::MissingField("version".to_string()));
+        issues.push(
+            "/version".to_string(),
+            ManifestValidationError::MissingField("version".to_string()),
+        );
     }
 
     if manifest.modes.is_empty() {
-        return Err(ManifestValidationError::EmptyModes);
+        issues.push("/modes".to_string(), ManifestValidationError::EmptyModes);
+    }
+
+    validate_var_names("/vars", &manifest.vars, &mut issues);
+
+    for (idx, mode_name) in manifest.mode_order.iter().enumerate() {
+        let path = pointer("/mode_order", idx);
+        if !manifest.modes.contains_key(mode_name) {
+            issues.push(
+                path,
+                ManifestValidationError::UnknownOrderedMode(mode_name.clone()),
+            );
+        } else if manifest.mode_order[..idx].contains(mode_name) {
+            issues.push(
+                path,
+                ManifestValidationError::DuplicateOrderedMode(mode_name.clone()),
+            );
+        }
+    }
+
+    for (mode_name, mode) in manifest.modes.iter() {
+        let mode_path = pointer("/modes", mode_name);
+
+        for (field, requirements) in [
+            ("requirements", &mode.requirements),
+            ("recommended", &mode.recommended),
+        ] {
+            if let Some(requirements) = requirements {
+                validate_requirements(
+                    mode_name,
+                    &pointer(&mode_path, field),
+                    requirements,
+                    &mut issues,
+                );
+            }
+        }
+
+        for (field, lists) in [
+            ("steps", &mode.steps),
+            ("prepend_steps", &mode.prepend_steps),
+            ("append_steps", &mode.append_steps),
+        ] {
+            validate_platform_steps(mode_name, &pointer(&mode_path, field), lists, &mut issues);
+        }
+
+        validate_var_names(&pointer(&mode_path, "vars"), &mode.vars, &mut issues);
+
+        if let Some(runtime_env) = &mode.runtime_env {
+            validate_runtime_env(
+                mode_name,
+                &pointer(&mode_path, "runtime_env"),
+                runtime_env,
+                &mut issues,
+            );
+        }
     }
 
+    resolve_inheritance(&mut manifest.modes, &mut issues);
+
     for (mode_name, mode) in manifest.modes.iter() {
         if mode.steps.is_empty() {
-            return Err(ManifestValidationError::ModeMissingSteps(mode_name.clone()));
+            issues.push(
+                pointer(&pointer("/modes", mode_name), "steps"),
+                ManifestValidationError::ModeMissingSteps(mode_name.clone()),
+            );
+        }
+    }
+
+    if issues.issues.is_empty() {
+        Ok(manifest)
+    } else {
+        Err(ManifestValidationErrors {
+            issues: issues.issues,
+        })
+    }
+}
+
+fn validate_platform_steps(
+    mode_name: &str,
+    path: &str,
+    lists: &BTreeMap<String, Vec<Step>>,
+    issues: &mut IssueCollector,
+) {
+    for (platform, steps) in lists.iter() {
+        let platform_path = pointer(path, platform);
+        issues.check(platform_path.clone(), validate_os_family(platform));
+
+        if steps.is_empty() {
+            issues.push(
+                platform_path.clone(),
+                ManifestValidationError::ModeMissingPlatformSteps {
+                    mode: mode_name.to_string(),
+                    platform: platform.clone(),
+                },
+            );
         }
 
-        if let Some(requirements) = &mode.requirements {
-            for constraint in &requirements.os {
-                validate_os_family(&constraint.family)?;
+        for (idx, step) in steps.iter().enumerate() {
+            for (field, message) in step.validate() {
+                issues.push(
+                    format!("{}/{field}", pointer(&platform_path, idx)),
+                    ManifestValidationError::InvalidStep(mode_name.to_string(), message),
+                );
             }
+        }
+    }
+}
 
-            for arch in &requirements.cpu_arch {
-                if arch.trim().is_empty() {
-                    return Err(ManifestValidationError::InvalidRequirement(
-                        mode_name.clone(),
-                        "cpu_arch entries must not be empty".to_string(),
-                    ));
+/// Flatten every mode in place: parents are resolved first, then the child's
+/// own settings are layered on top and `prepend_steps`/`append_steps` are
+/// folded into `steps`. Unknown parents and cycles are reported at the
+/// offending `extends` and leave the mode un-inherited.
+fn resolve_inheritance(modes: &mut BTreeMap<String, Mode>, issues: &mut IssueCollector) {
+    let names: Vec<String> = modes.keys().cloned().collect();
+    let mut resolved = BTreeSet::new();
+    for name in names {
+        resolve_mode(&name, modes, &mut resolved, &mut Vec::new(), issues);
+    }
+}
+
+fn resolve_mode(
+    name: &str,
+    modes: &mut BTreeMap<String, Mode>,
+    resolved: &mut BTreeSet<String>,
+    chain: &mut Vec<String>,
+    issues: &mut IssueCollector,
+) {
+    if resolved.contains(name) {
+        return;
+    }
+
+    let extends = modes[name].extends.clone();
+    let mut parent = None;
+    chain.push(name.to_string());
+    if let Some(parent_name) = extends {
+        let extends_path = pointer(&pointer("/modes", name), "extends");
+        if !modes.contains_key(&parent_name) {
+            issues.push(
+                extends_path,
+                ManifestValidationError::UnknownParentMode {
+                    mode: name.to_string(),
+                    parent: parent_name,
+                },
+            );
+        } else if let Some(start) = chain.iter().position(|n| n == &parent_name) {
+            let mut cycle = chain[start..].to_vec();
+            cycle.push(parent_name);
+            issues.push(
+                extends_path,
+                ManifestValidationError::InheritanceCycle(cycle),
+            );
+        } else {
+            resolve_mode(&parent_name, modes, resolved, chain, issues);
+            parent = Some(modes[&parent_name].clone());
+        }
+    }
+    chain.pop();
+
+    let mode = modes.get_mut(name).expect("mode exists");
+    if let Some(parent) = parent {
+        inherit_from(mode, parent);
+    }
+    for (platform, steps) in std::mem::take(&mut mode.prepend_steps) {
+        mode.steps.entry(platform).or_default().splice(0..0, steps);
+    }
+    for (platform, steps) in std::mem::take(&mut mode.append_steps) {
+        mode.steps.entry(platform).or_default().extend(steps);
+    }
+    resolved.insert(name.to_string());
+}
+
+fn inherit_from(mode: &mut Mode, parent: Mode) {
+    if mode.requirements.is_none() {
+        mode.requirements = parent.requirements;
+    }
+    if mode.recommended.is_none() {
+        mode.recommended = parent.recommended;
+    }
+    if mode.runtime_env.is_none() {
+        mode.runtime_env = parent.runtime_env;
+    }
+
+    let mut vars = parent.vars;
+    vars.append(&mut mode.vars);
+    mode.vars = vars;
+
+    let mut steps = parent.steps;
+    steps.append(&mut mode.steps);
+    mode.steps = steps;
+}
+
+fn suggestion_hint(suggestion: &Option<String>) -> String {
+    suggestion
+        .as_ref()
+        .map(|s| format!(", did you mean '{s}'?"))
+        .unwrap_or_default()
+}
+
+/// Walk a parsed document against the manifest schema and report every key
+/// the manifest types do not know about. Running this before the typed parse
+/// gives located, did-you-mean errors instead of serde's first-failure message
+/// (which for the untagged `Step` enum is "did not match any variant").
+pub fn check_unknown_fields(document: &serde_json::Value) -> Result<(), ManifestValidationErrors> {
+    let schema = serde_json::to_value(manifest_schema()).expect("schema serializes to JSON");
+    let mut issues = IssueCollector::default();
+    check_value_fields(document, &schema, &schema["definitions"], "", &mut issues);
+
+    if issues.issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ManifestValidationErrors {
+            issues: issues.issues,
+        })
+    }
+}
+
+fn check_value_fields(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+    definitions: &serde_json::Value,
+    path: &str,
+    issues: &mut IssueCollector,
+) {
+    let schema = resolve_schema(schema, definitions);
+
+    if let Some(variants) = schema["anyOf"].as_array() {
+        let variants: Vec<&serde_json::Value> = variants
+            .iter()
+            .map(|variant| resolve_schema(variant, definitions))
+            .filter(|variant| variant["type"] != "null")
+            .collect();
+        match variants.as_slice() {
+            [] => {}
+            [only] => check_value_fields(value, only, definitions, path, issues),
+            _ => check_variant_fields(value, &variants, definitions, path, issues),
+        }
+        return;
+    }
+
+    match value {
+        serde_json::Value::Object(map) => {
+            let properties = schema["properties"].as_object();
+            for (key, child) in map {
+                let child_path = pointer(path, key);
+                if let Some(property) = properties.and_then(|props| props.get(key)) {
+                    check_value_fields(child, property, definitions, &child_path, issues);
+                } else if schema["additionalProperties"].is_object() {
+                    check_value_fields(
+                        child,
+                        &schema["additionalProperties"],
+                        definitions,
+                        &child_path,
+                        issues,
+                    );
+                } else if schema["additionalProperties"] == false {
+                    let known = properties
+                        .map(|props| props.keys().map(String::as_str).collect())
+                        .unwrap_or_default();
+                    issues.push(child_path, unknown_field(key, known));
                 }
             }
         }
-
-        for (platform, steps) in mode.steps.iter() {
-            if steps.is_empty() {
-                return Err(ManifestValidationError::ModeMissingPlatformSteps {
-                    mode: mode_name.clone(),
-                    platform: platform.clone(),
-                });
+        serde_json::Value::Array(items) if schema["items"].is_object() => {
+            for (idx, item) in items.iter().enumerate() {
+                check_value_fields(
+                    item,
+                    &schema["items"],
+                    definitions,
+                    &pointer(path, idx),
+                    issues,
+                );
             }
+        }
+        _ => {}
+    }
+}
+
+/// Untagged enums (steps) are matched by their required key; when no variant
+/// matches, every unrecognized key is reported against all variants' keys.
+fn check_variant_fields(
+    value: &serde_json::Value,
+    variants: &[&serde_json::Value],
+    definitions: &serde_json::Value,
+    path: &str,
+    issues: &mut IssueCollector,
+) {
+    let Some(map) = value.as_object() else {
+        return;
+    };
+
+    let matching = variants.iter().find(|variant| {
+        variant["required"].as_array().is_some_and(|required| {
+            required
+                .iter()
+                .all(|key| key.as_str().is_some_and(|key| map.contains_key(key)))
+        })
+    });
+    if let Some(variant) = matching {
+        check_value_fields(value, variant, definitions, path, issues);
+        return;
+    }
+
+    let known: Vec<&str> = variants
+        .iter()
+        .filter_map(|variant| variant["properties"].as_object())
+        .flat_map(|props| props.keys().map(String::as_str))
+        .collect();
+    for key in map.keys() {
+        if !known.contains(&key.as_str()) {
+            issues.push(pointer(path, key), unknown_field(key, known.clone()));
+        }
+    }
+}
+
+fn resolve_schema<'a>(
+    schema: &'a serde_json::Value,
+    definitions: &'a serde_json::Value,
+) -> &'a serde_json::Value {
+    match schema["$ref"]
+        .as_str()
+        .and_then(|reference| reference.strip_prefix("#/definitions/"))
+    {
+        Some(name) => &definitions[name],
+        None => schema,
+    }
+}
+
+fn unknown_field(field: &str, known: Vec<&str>) -> ManifestValidationError {
+    ManifestValidationError::UnknownField {
+        field: field.to_string(),
+        suggestion: closest_match(field, known),
+    }
+}
 
-            validate_os_family(platform)?;
+fn closest_match<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), candidate))
+        .filter(|(score, _)| *score > 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+pub const SUPPORTED_PLATFORMS: [&str; 3] = ["windows", "macos", "linux"];
+
+fn validate_os_family(os: &str) -> Result<(), ManifestValidationError> {
+    if SUPPORTED_PLATFORMS.contains(&os) {
+        Ok(())
+    } else {
+        Err(ManifestValidationError::UnsupportedPlatform(os.to_string()))
+    }
+}
 
-            for step in steps {
-                step.validate().map_err(|err| {
-                    ManifestValidationError::InvalidStep(mode_name.clone(), err.to_string())
-                })?;
+/// OS constraints may name a platform family or a Linux distribution `ID`
+/// from os-release (lowercase letters, digits, `.`, `_` and `-`).
+fn validate_constraint_family(
+    mode_name: &str,
+    family: &str,
+) -> Result<(), ManifestValidationError> {
+    if validate_os_family(family).is_ok() {
+        return Ok(());
+    }
+
+    let is_distro_id = family
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'));
+    if is_distro_id {
+        return Ok(());
+    }
+
+    Err(ManifestValidationError::InvalidRequirement(
+        mode_name.to_string(),
+        format!("'{family}' is not a platform or distribution id"),
+    ))
+}
+
+/// Distribution `ID`s that OS constraints may name without a warning.
+pub const KNOWN_DISTROS: [&str; 30] = [
+    "almalinux",
+    "alpine",
+    "amzn",
+    "arch",
+    "azurelinux",
+    "centos",
+    "debian",
+    "elementary",
+    "endeavouros",
+    "fedora",
+    "gentoo",
+    "kali",
+    "linuxmint",
+    "manjaro",
+    "mariner",
+    "nixos",
+    "ol",
+    "opensuse",
+    "opensuse-leap",
+    "opensuse-tumbleweed",
+    "photon",
+    "pop",
+    "raspbian",
+    "rhel",
+    "rocky",
+    "sles",
+    "slackware",
+    "ubuntu",
+    "void",
+    "zorin",
+];
+
+/// Non-fatal problems in a valid manifest: OS constraint families that are
+/// neither a platform nor a well-known distribution `ID`. Any lowercase
+/// `ID` is accepted, since distributions are open-ended, but an unfamiliar
+/// one is usually a typo such as `macso`.
+pub fn manifest_warnings(manifest: &Manifest) -> Vec<ValidationIssue> {
+    let mut issues = IssueCollector::default();
+    for (mode_name, mode) in &manifest.modes {
+        let mode_path = pointer("/modes", mode_name);
+        for (field, requirements) in [
+            ("requirements", &mode.requirements),
+            ("recommended", &mode.recommended),
+        ] {
+            if let Some(requirements) = requirements {
+                warn_unknown_families(&pointer(&mode_path, field), requirements, &mut issues);
             }
         }
+    }
+    issues.issues
+}
 
-        if let Some(runtime_env) = &mode.runtime_env {
-            validate_runtime_env(mode_name, runtime_env)?;
+fn warn_unknown_families(path: &str, requirements: &Requirements, issues: &mut IssueCollector) {
+    for (idx, constraint) in requirements.os.iter().enumerate() {
+        let family = constraint.family.as_str();
+        if SUPPORTED_PLATFORMS.contains(&family) || KNOWN_DISTROS.contains(&family) {
+            continue;
         }
+        let known = SUPPORTED_PLATFORMS.into_iter().chain(KNOWN_DISTROS);
+        issues.push(
+            pointer(&pointer(path, "os"), idx),
+            ManifestValidationError::UnknownOsFamily {
+                family: family.to_string(),
+                suggestion: closest_match(family, known),
+            },
+        );
     }
 
-    Ok(manifest)
+    for (key, children) in [("all", &requirements.all), ("any", &requirements.any)] {
+        for (idx, child) in children.iter().enumerate() {
+            warn_unknown_families(&pointer(&pointer(path, key), idx), child, issues);
+        }
+    }
+    if let Some(child) = &requirements.not {
+        warn_unknown_families(&pointer(path, "not"), child, issues);
+    }
 }
 
-fn validate_os_family(os: &str) -> Result<(), ManifestValidationError> {
-    match os {
-        "windows" | "macos" => Ok(()),
-        other => Err(ManifestValidationError::UnsupportedPlatform(
-            other.to_string(),
-        )),
+/// Variable names are referenced as `{{name}}`, so they must be non-empty and
+/// free of whitespace and braces.
+fn validate_var_names(path: &str, vars: &BTreeMap<String, String>, issues: &mut IssueCollector) {
+    for name in vars.keys() {
+        let valid = !name.is_empty()
+            && !name
+                .chars()
+                .any(|c| c.is_whitespace() || c == '{' || c == '}');
+        if !valid {
+            issues.push(
+                pointer(path, name),
+                ManifestValidationError::InvalidVariable(name.clone()),
+            );
+        }
+    }
+}
+
+fn validate_requirements(
+    mode_name: &str,
+    path: &str,
+    requirements: &Requirements,
+    issues: &mut IssueCollector,
+) {
+    for (idx, constraint) in requirements.os.iter().enumerate() {
+        issues.check(
+            pointer(&pointer(path, "os"), idx),
+            validate_constraint_family(mode_name, &constraint.family),
+        );
+    }
+
+    for (idx, arch) in requirements.cpu_arch.iter().enumerate() {
+        if arch.trim().is_empty() {
+            issues.push(
+                pointer(&pointer(path, "cpu_arch"), idx),
+                ManifestValidationError::InvalidRequirement(
+                    mode_name.to_string(),
+                    "cpu_arch entries must not be empty".to_string(),
+                ),
+            );
+        }
+    }
+
+    for (idx, manager) in requirements.pkg_managers.iter().enumerate() {
+        if manager.trim().is_empty() {
+            issues.push(
+                pointer(&pointer(path, "pkg_managers"), idx),
+                ManifestValidationError::InvalidRequirement(
+                    mode_name.to_string(),
+                    "pkg_managers entries must not be empty".to_string(),
+                ),
+            );
+        }
+    }
+
+    for (idx, feature) in requirements.cpu_features.iter().enumerate() {
+        let valid = !feature.is_empty()
+            && feature
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.'));
+        if !valid {
+            issues.push(
+                pointer(&pointer(path, "cpu_features"), idx),
+                ManifestValidationError::InvalidRequirement(
+                    mode_name.to_string(),
+                    format!("'{feature}' is not a CPU feature name"),
+                ),
+            );
+        }
+    }
+
+    for (key, children) in [("all", &requirements.all), ("any", &requirements.any)] {
+        for (idx, child) in children.iter().enumerate() {
+            validate_requirements(mode_name, &pointer(&pointer(path, key), idx), child, issues);
+        }
+    }
+    if let Some(child) = &requirements.not {
+        validate_requirements(mode_name, &pointer(path, "not"), child, issues);
     }
 }
 
 fn validate_runtime_env(
     mode_name: &str,
+    path: &str,
     runtime: &RuntimeEnv,
-) -> Result<(), ManifestValidationError> {
+    issues: &mut IssueCollector,
+) {
     if runtime.root.as_os_str().is_empty() {
-        return Err(ManifestValidationError::InvalidRequirement(
-            mode_name.to_string(),
-            "runtime_env root cannot be empty".to_string(),
-        ));
+        issues.push(
+            pointer(path, "root"),
+            ManifestValidationError::InvalidRequirement(
+                mode_name.to_string(),
+                "runtime_env root cannot be empty".to_string(),
+            ),
+        );
     }
 
     match runtime.kind {
         RuntimeEnvType::NodeLocal => {
             if runtime.node.is_none() {
-                return Err(ManifestValidationError::InvalidRequirement(
-                    mode_name.to_string(),
-                    "runtime_env.node must be provided for node_local".to_string(),
-                ));
+                issues.push(
+                    pointer(path, "node"),
+                    ManifestValidationError::InvalidRequirement(
+                        mode_name.to_string(),
+                        "runtime_env.node must be provided for node_local".to_string(),
+                    ),
+                );
             }
         }
         RuntimeEnvType::PythonVenv => {
             if runtime.python.is_none() {
-                return Err(ManifestValidationError::InvalidRequirement(
-                    mode_name.to_string(),
-                    "runtime_env.python must be provided for python_venv".to_string(),
-                ));
+                issues.push(
+                    pointer(path, "python"),
+                    ManifestValidationError::InvalidRequirement(
+                        mode_name.to_string(),
+                        "runtime_env.python must be provided for python_venv".to_string(),
+                    ),
+                );
             }
         }
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        Manifest, ManifestValidationError, Mode, Requirements, Step, load_manifest,
-        parse_os_constraint,
+        Manifest, ManifestError, ManifestFormat, ManifestValidationError, ManifestValidationErrors,
+        Mode, OS_CONSTRAINT_PATTERN, Requirements, Step, VersionComparator, VersionOp,
+        check_unknown_fields, load_manifest, manifest_schema, manifest_warnings,
+        parse_command_requirement, parse_libc_constraint, parse_manifest, parse_os_constraint,
     };
     use std::collections::BTreeMap;
 
@@ -346,7 +1645,177 @@ mod tests {
     fn parses_os_constraint_with_version() {
         let parsed = parse_os_constraint("windows>=10").expect("should parse valid constraint");
         assert_eq!(parsed.family, "windows");
-        assert_eq!(parsed.min_version.as_deref(), Some("10"));
+        assert_eq!(
+            parsed.comparators,
+            vec![VersionComparator {
+                op: VersionOp::Ge,
+                version: "10".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_os_constraint_ranges_and_wildcards() {
+        let parsed = parse_os_constraint("macos >= 12, <15").expect("range should parse");
+        assert_eq!(parsed.family, "macos");
+        let ops: Vec<VersionOp> = parsed.comparators.iter().map(|c| c.op).collect();
+        assert_eq!(ops, vec![VersionOp::Ge, VersionOp::Lt]);
+        assert_eq!(parsed.to_string(), "macos>=12,<15");
+
+        let parsed = parse_os_constraint("windows==10.*").expect("wildcard should parse");
+        assert_eq!(parsed.comparators[0].wildcard_prefix(), Some("10"));
+        assert_eq!(parse_os_constraint("linux").unwrap().comparators, vec![]);
+    }
+
+    #[test]
+    fn parses_command_requirements() {
+        let parsed = parse_command_requirement("git>=2.30").expect("should parse");
+        assert_eq!(parsed.name, "git");
+        assert_eq!(parsed.comparators[0].op, VersionOp::Ge);
+        assert_eq!(
+            parse_command_requirement(" g++ >=11,<14")
+                .unwrap()
+                .to_string(),
+            "g++>=11,<14"
+        );
+        assert!(
+            parse_command_requirement("brew")
+                .unwrap()
+                .comparators
+                .is_empty()
+        );
+        assert!(parse_command_requirement("my tool").is_err());
+        assert!(parse_command_requirement(">=1").is_err());
+    }
+
+    #[test]
+    fn warns_about_unknown_os_families() {
+        let json = r#"{
+            "name": "app",
+            "version": "1.0.0",
+            "modes": {
+                "full": {
+                    "requirements": {
+                        "os": ["macso>=13", "ubuntu>=22.04", "linux"],
+                        "any": [ { "os": ["myco-linux"] } ]
+                    },
+                    "steps": { "macos": [ { "run": "echo" } ] }
+                }
+            }
+        }"#;
+        let manifest = parse_manifest(json, ManifestFormat::Json).expect("warnings are not errors");
+        let warnings: Vec<String> = manifest_warnings(&manifest)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "/modes/full/requirements/os/0: 'macso' is not a known platform or distribution id, did you mean 'macos'?",
+                "/modes/full/requirements/any/0/os/0: 'myco-linux' is not a known platform or distribution id",
+            ]
+        );
+    }
+
+    #[test]
+    fn required_commands_include_recommended_ones() {
+        let json = r#"{
+            "name": "app",
+            "version": "1.0.0",
+            "modes": {
+                "full": {
+                    "requirements": { "commands": ["git"], "any": [ { "commands": ["node>=18"] } ] },
+                    "recommended": { "commands": ["docker"], "not": { "commands": ["podman"] } },
+                    "steps": { "macos": [ { "run": "echo" } ] }
+                }
+            }
+        }"#;
+        let manifest = parse_manifest(json, ManifestFormat::Json).expect("manifest should parse");
+        assert_eq!(
+            manifest.required_commands().into_iter().collect::<Vec<_>>(),
+            vec!["docker", "git", "node", "podman"]
+        );
+    }
+
+    #[test]
+    fn parses_libc_constraints() {
+        let parsed = parse_libc_constraint("GLIBC >= 2.31").expect("should parse");
+        assert_eq!(parsed.family, "glibc");
+        assert_eq!(parsed.to_string(), "glibc>=2.31");
+        assert!(
+            parse_libc_constraint("musl")
+                .unwrap()
+                .comparators
+                .is_empty()
+        );
+
+        let err = parse_libc_constraint("uclibc>=1").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid libc constraint 'uclibc>=1': unknown libc family 'uclibc' (expected glibc or musl)"
+        );
+        assert!(parse_libc_constraint(">=2.31").is_err());
+    }
+
+    #[test]
+    fn validates_nested_requirement_expressions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "name": "demo",
+                "version": "1.0.0",
+                "modes": {
+                    "full": {
+                        "requirements": {
+                            "any": [ { "cpu_arch": ["x64"] }, { "os": ["Mac OS"] } ],
+                            "not": { "all": [ { "pkg_managers": [""] } ] }
+                        },
+                        "steps": { "linux": [ { "run": "echo" } ] }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let err = load_manifest(&path).expect_err("nested entries are validated");
+        let errors = err
+            .downcast_ref::<ManifestValidationErrors>()
+            .expect("validation errors");
+        let paths: Vec<&str> = errors.issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/modes/full/requirements/any/1/os/0",
+                "/modes/full/requirements/not/all/0/pkg_managers/0",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_os_constraints() {
+        for raw in [
+            ">=10",
+            "macos>=",
+            "macos>=12,15",
+            "macos=>12",
+            "windows>=10.*",
+            "windows==1*.0",
+            "macos>=12 <15",
+        ] {
+            assert!(
+                parse_os_constraint(raw).is_err(),
+                "'{raw}' should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_distro_constraint() {
+        let parsed = parse_os_constraint("Ubuntu>=22.04").expect("should parse distro constraint");
+        assert_eq!(parsed.family, "ubuntu");
+        assert_eq!(parsed.comparators[0].version, "22.04");
     }
 
     #[test]
@@ -363,6 +1832,7 @@ mod tests {
                             os: vec![parse_os_constraint("windows>=10").unwrap()],
                             cpu_arch: vec!["x64".to_string()],
                             ram_gb: Some(8),
+                            ..Default::default()
                         }),
                         steps: {
                             let mut steps = BTreeMap::new();
@@ -374,10 +1844,12 @@ mod tests {
                             );
                             steps
                         },
+                        ..Default::default()
                     },
                 );
                 modes
             },
+            ..Default::default()
         };
 
         validate_manifest(manifest).expect("manifest should be valid");
@@ -395,14 +1867,20 @@ mod tests {
                     Mode {
                         requirements: None,
                         steps: BTreeMap::new(),
+                        ..Default::default()
                     },
                 );
                 modes
             },
+            ..Default::default()
         };
 
         let err = validate_manifest(manifest).expect_err("manifest should be invalid");
-        matches!(err, ManifestValidationError::ModeMissingSteps(_));
+        assert!(matches!(
+            err.issues[0].error,
+            ManifestValidationError::ModeMissingSteps(_)
+        ));
+        assert_eq!(err.issues[0].path, "/modes/full/steps");
     }
 
     #[test]
@@ -413,20 +1891,58 @@ mod tests {
             modes: {
                 let mut modes = BTreeMap::new();
                 let mut steps = BTreeMap::new();
-                steps.insert("linux".to_string(), vec![Step::Run { run: "echo".into() }]);
+                steps.insert(
+                    "solaris".to_string(),
+                    vec![Step::Run { run: "echo".into() }],
+                );
                 modes.insert(
                     "full".to_string(),
                     Mode {
                         requirements: None,
                         steps,
+                        ..Default::default()
                     },
                 );
                 modes
             },
+            ..Default::default()
         };
 
         let err = validate_manifest(manifest).expect_err("manifest should be invalid");
-        matches!(err, ManifestValidationError::UnsupportedPlatform(_));
+        assert!(matches!(
+            err.issues[0].error,
+            ManifestValidationError::UnsupportedPlatform(_)
+        ));
+    }
+
+    #[test]
+    fn accepts_linux_platform_and_constraint() {
+        let manifest = Manifest {
+            name: "demo".to_string(),
+            version: "1.0.0".to_string(),
+            modes: {
+                let mut modes = BTreeMap::new();
+                let mut steps = BTreeMap::new();
+                steps.insert("linux".to_string(), vec![Step::Run { run: "echo".into() }]);
+                modes.insert(
+                    "full".to_string(),
+                    Mode {
+                        requirements: Some(Requirements {
+                            os: vec![parse_os_constraint("linux").unwrap()],
+                            cpu_arch: vec![],
+                            ram_gb: None,
+                            ..Default::default()
+                        }),
+                        steps,
+                        ..Default::default()
+                    },
+                );
+                modes
+            },
+            ..Default::default()
+        };
+
+        validate_manifest(manifest).expect("linux manifest should be valid");
     }
 
     #[test]
@@ -440,7 +1956,349 @@ mod tests {
         assert!(err.to_string().contains("validating manifest"));
     }
 
-    fn validate_manifest(manifest: Manifest) -> Result<Manifest, ManifestValidationError> {
+    #[test]
+    fn loads_yaml_and_toml_by_extension() {
+        let dir = tempfile::tempdir().expect("tempdir should be created");
+        let yaml_path = dir.path().join("manifest.yml");
+        std::fs::write(
+            &yaml_path,
+            r#"
+name: demo
+version: "1.0.0"
+modes:
+  full:
+    requirements:
+      os: ["linux", "macos>=13"]
+      ram_gb: 4
+    steps:
+      linux:
+        - run: |
+            echo one
+            echo two
+        - download:
+            url: https://example.com/file.zip
+            dest: artifacts/file.zip
+"#,
+        )
+        .unwrap();
+        let yaml = load_manifest(&yaml_path).expect("yaml manifest should load");
+        let steps = &yaml.modes["full"].steps["linux"];
+        assert!(matches!(&steps[0], Step::Run { run } if run.contains("echo two")));
+        assert!(matches!(steps[1], Step::Download { .. }));
+
+        let toml_path = dir.path().join("manifest.toml");
+        std::fs::write(
+            &toml_path,
+            r#"
+name = "demo"
+version = "1.0.0"
+
+[modes.full.requirements]
+os = ["windows>=10"]
+
+[[modes.full.steps.windows]]
+run = "echo hi"
+
+[[modes.full.steps.windows]]
+extract = { archive = "a.zip", dest = "out" }
+"#,
+        )
+        .unwrap();
+        let toml = load_manifest(&toml_path).expect("toml manifest should load");
+        let steps = &toml.modes["full"].steps["windows"];
+        assert!(matches!(steps[0], Step::Run { .. }));
+        assert!(matches!(steps[1], Step::Extract { .. }));
+    }
+
+    #[test]
+    fn parse_errors_report_line_and_column() {
+        let json = "{\n  \"name\": \"demo\",\n  \"version\": 1,\n}";
+        let yaml = "name: demo\nversion: [1\n";
+        let toml = "name = \"demo\"\nversion = \n";
+
+        for (data, format, expected_line) in [
+            (json, ManifestFormat::Json, 3),
+            (yaml, ManifestFormat::Yaml, 2),
+            (toml, ManifestFormat::Toml, 2),
+        ] {
+            match parse_manifest(data, format).expect_err("should fail to parse") {
+                ManifestError::Parse { line, column, .. } => {
+                    assert_eq!(line, Some(expected_line), "{format} line");
+                    assert!(column.is_some(), "{format} column");
+                }
+                other => panic!("unexpected error for {format}: {other}"),
+            }
+        }
+    }
+
+    #[test]
+    fn format_flag_values_parse() {
+        assert_eq!(
+            "YML".parse::<ManifestFormat>().unwrap(),
+            ManifestFormat::Yaml
+        );
+        assert!("ini".parse::<ManifestFormat>().is_err());
+    }
+
+    #[test]
+    fn schema_covers_step_variants_and_os_syntax() {
+        let schema = serde_json::to_value(manifest_schema()).unwrap();
+        let definitions = &schema["definitions"];
+
+        let variants = definitions["Step"]["anyOf"]
+            .as_array()
+            .expect("steps are anyOf");
+        let keys: Vec<&str> = variants
+            .iter()
+            .flat_map(|variant| variant["required"].as_array().unwrap())
+            .map(|key| key.as_str().unwrap())
+            .collect();
+        assert_eq!(keys, ["run", "download", "extract", "template_config"]);
+
+        assert_eq!(
+            definitions["OsConstraint"]["pattern"].as_str(),
+            Some(OS_CONSTRAINT_PATTERN)
+        );
+        let steps = &definitions["Mode"]["properties"]["steps"];
+        assert!(steps["properties"]["linux"].is_object());
+        assert_eq!(steps["additionalProperties"], serde_json::json!(false));
+    }
+
+    #[test]
+    fn collects_every_issue_with_json_pointer_paths() {
+        let json = r#"{
+            "name": "",
+            "version": "1.0.0",
+            "modes": {
+                "full": {
+                    "requirements": { "cpu_arch": ["x64", " "] },
+                    "steps": {
+                        "macos": [
+                            {"run": "echo ok"},
+                            {"run": "echo ok"},
+                            {"run": "echo ok"},
+                            {"download": {"url": "", "dest": ""}}
+                        ],
+                        "solaris": [{"run": "echo"}]
+                    }
+                },
+                "a/b": { "steps": { "linux": [] } }
+            }
+        }"#;
+        let manifest = parse_manifest(json, ManifestFormat::Json).unwrap();
+
+        let err = validate_manifest(manifest).expect_err("manifest should be invalid");
+        let paths: Vec<&str> = err.issues.iter().map(|issue| issue.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/name",
+                "/modes/a~1b/steps/linux",
+                "/modes/full/requirements/cpu_arch/1",
+                "/modes/full/steps/macos/3/download/url",
+                "/modes/full/steps/macos/3/download/dest",
+                "/modes/full/steps/solaris",
+            ]
+        );
+
+        let rendered = serde_json::to_value(&err.issues[3]).unwrap();
+        assert_eq!(
+            rendered["message"],
+            "mode 'full' has an invalid step: download url cannot be empty"
+        );
+    }
+
+    #[test]
+    fn download_steps_accept_a_urls_list() {
+        let step: Step = serde_json::from_str(
+            r#"{"download": {"urls": ["https://a.example.com/x.zip", "https://b.example.com/x.zip"], "dest": "x.zip"}}"#,
+        )
+        .unwrap();
+        assert!(step.validate().is_empty());
+        assert_eq!(
+            step.description(),
+            "Download https://a.example.com/x.zip to x.zip (1 fallback URL)"
+        );
+
+        let step: Step =
+            serde_json::from_str(r#"{"download": {"urls": [""], "dest": "x.zip"}}"#).unwrap();
+        let fields: Vec<&str> = step
+            .validate()
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect();
+        assert_eq!(fields, vec!["download/urls"]);
+    }
+
+    #[test]
+    fn reports_unknown_fields_with_suggestions() {
+        let json = r#"{
+            "name": "demo",
+            "version": "1.0.0",
+            "modes": {
+                "full": {
+                    "requirements": { "ram_gbs": 8 },
+                    "steps": {
+                        "macos": [
+                            {"run": "echo ok"},
+                            {"downlaod": {"url": "https://example.com/a", "dest": "a"}},
+                            {"download": {"url": "https://example.com/a", "dst": "a"}}
+                        ]
+                    },
+                    "runtime_env": { "type": "node_local", "node": { "verison": "20" } }
+                }
+            }
+        }"#;
+        let document: serde_json::Value = serde_json::from_str(json).unwrap();
+
+        let err = check_unknown_fields(&document).expect_err("unknown fields should be rejected");
+        let rendered: Vec<String> = err.issues.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rendered,
+            [
+                "/modes/full/requirements/ram_gbs: unknown field 'ram_gbs', did you mean 'ram_gb'?",
+                "/modes/full/runtime_env/node/verison: unknown field 'verison', did you mean 'version'?",
+                "/modes/full/steps/macos/1/downlaod: unknown field 'downlaod', did you mean 'download'?",
+                "/modes/full/steps/macos/2/download/dst: unknown field 'dst', did you mean 'dest'?",
+            ]
+        );
+    }
+
+    #[test]
+    fn typed_parse_rejects_unknown_fields() {
+        let json = r#"{"name": "demo", "version": "1", "modes": {}, "extra": true}"#;
+        assert!(parse_manifest(json, ManifestFormat::Json).is_err());
+        let step: Result<Step, _> = serde_json::from_str(r#"{"run": "echo", "shell": "bash"}"#);
+        assert!(step.is_err());
+    }
+
+    #[test]
+    fn load_manifest_rejects_unknown_yaml_fields() {
+        let dir = tempfile::tempdir().expect("tempdir should be created");
+        let path = dir.path().join("manifest.yaml");
+        std::fs::write(
+            &path,
+            "name: demo\nversion: '1'\nmodes:\n  full:\n    stepz:\n      linux:\n        - run: echo\n",
+        )
+        .unwrap();
+
+        let err = load_manifest(&path).expect_err("unknown field should fail");
+        let errors = err
+            .downcast_ref::<ManifestValidationErrors>()
+            .expect("should surface located issues");
+        assert_eq!(errors.issues[0].path, "/modes/full/stepz");
+        assert!(
+            errors.issues[0]
+                .to_string()
+                .contains("did you mean 'steps'?")
+        );
+    }
+
+    #[test]
+    fn flattens_extended_modes() {
+        let json = r#"{
+            "name": "demo",
+            "version": "1.0.0",
+            "modes": {
+                "base": {
+                    "requirements": { "ram_gb": 4 },
+                    "vars": { "channel": "stable", "port": "3000" },
+                    "steps": {
+                        "macos": [{"run": "brew install node"}, {"run": "npm run build"}],
+                        "windows": [{"run": "winget install node"}]
+                    }
+                },
+                "full": {
+                    "extends": "base",
+                    "vars": { "channel": "full" },
+                    "prepend_steps": { "macos": [{"run": "brew install postgresql"}] },
+                    "append_steps": { "macos": [{"run": "npm run migrate"}] },
+                    "steps": { "windows": [{"run": "winget install postgresql"}] }
+                },
+                "kiosk": {
+                    "extends": "full",
+                    "requirements": { "ram_gb": 2 },
+                    "append_steps": { "linux": [{"run": "systemctl enable kiosk"}] }
+                }
+            }
+        }"#;
+        let manifest = validate_manifest(parse_manifest(json, ManifestFormat::Json).unwrap())
+            .expect("manifest should be valid");
+
+        let commands = |mode: &str, platform: &str| -> Vec<String> {
+            manifest.modes[mode].steps[platform]
+                .iter()
+                .filter_map(Step::command)
+                .collect()
+        };
+        assert_eq!(
+            commands("full", "macos"),
+            [
+                "brew install postgresql",
+                "brew install node",
+                "npm run build",
+                "npm run migrate"
+            ]
+        );
+        assert_eq!(commands("full", "windows"), ["winget install postgresql"]);
+        assert_eq!(commands("kiosk", "macos"), commands("full", "macos"));
+        assert_eq!(commands("kiosk", "linux"), ["systemctl enable kiosk"]);
+
+        let full = &manifest.modes["full"];
+        assert_eq!(full.requirements.as_ref().unwrap().ram_gb, Some(4));
+        assert_eq!(full.vars["channel"], "full");
+        assert_eq!(full.vars["port"], "3000");
+        assert!(full.prepend_steps.is_empty() && full.append_steps.is_empty());
+        assert_eq!(
+            manifest.modes["kiosk"]
+                .requirements
+                .as_ref()
+                .unwrap()
+                .ram_gb,
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_duplicate_mode_order_entries() {
+        let json = r#"{
+            "name": "demo",
+            "version": "1.0.0",
+            "mode_order": ["kiosk", "server", "kiosk"],
+            "modes": { "kiosk": { "steps": { "linux": [{"run": "echo"}] } } }
+        }"#;
+        let err = validate_manifest(parse_manifest(json, ManifestFormat::Json).unwrap())
+            .expect_err("mode_order errors should be reported");
+        let paths: Vec<&str> = err.issues.iter().map(|issue| issue.path.as_str()).collect();
+        assert_eq!(paths, ["/mode_order/1", "/mode_order/2"]);
+    }
+
+    #[test]
+    fn rejects_unknown_parents_and_cycles() {
+        let json = r#"{
+            "name": "demo",
+            "version": "1.0.0",
+            "modes": {
+                "a": { "extends": "b", "steps": { "macos": [{"run": "a"}] } },
+                "b": { "extends": "a", "steps": { "macos": [{"run": "b"}] } },
+                "c": { "extends": "missing", "steps": { "macos": [{"run": "c"}] } },
+                "d": { "extends": "d", "steps": { "macos": [{"run": "d"}] } }
+            }
+        }"#;
+        let err = validate_manifest(parse_manifest(json, ManifestFormat::Json).unwrap())
+            .expect_err("inheritance errors should be reported");
+        let rendered: Vec<String> = err.issues.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rendered,
+            [
+                "/modes/b/extends: mode inheritance cycle: a -> b -> a",
+                "/modes/c/extends: mode 'c' extends unknown mode 'missing'",
+                "/modes/d/extends: mode inheritance cycle: d -> d",
+            ]
+        );
+    }
+
+    fn validate_manifest(manifest: Manifest) -> Result<Manifest, ManifestValidationErrors> {
         super::validate_manifest(manifest)
     }
 }
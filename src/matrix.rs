@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::env_detect::{Environment, load_environment};
+use crate::manifest::Manifest;
+use crate::planner::{PlannerError, plan_install};
+
+/// Outcome of planning a manifest against one saved environment profile.
+#[derive(Debug, Serialize, Clone)]
+pub struct MatrixRow {
+    pub profile: String,
+    pub os: String,
+    pub os_version: String,
+    pub cpu_arch: String,
+    pub ram_gb: u64,
+    /// The selected mode, or `None` when no mode is compatible.
+    pub chosen_mode: Option<String>,
+    /// Why each mode was rejected when none was chosen.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+/// Expected mode per profile; `None` asserts that no mode is compatible.
+pub type Assertions = BTreeMap<String, Option<String>>;
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct AssertionFailure {
+    pub profile: String,
+    pub expected: Option<String>,
+    /// `None` both when no mode was chosen and when the profile is missing.
+    pub actual: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+    Markdown,
+}
+
+#[derive(Debug, Error)]
+#[error("unsupported report format '{0}' (expected csv, json, or markdown)")]
+pub struct UnknownReportFormat(String);
+
+impl FromStr for ReportFormat {
+    type Err = UnknownReportFormat;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            other => Err(UnknownReportFormat(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+            ReportFormat::Markdown => "markdown",
+        })
+    }
+}
+
+/// Load every `*.json` environment profile in `dir`, named by file stem and
+/// sorted by name.
+pub fn load_profiles(dir: &Path) -> anyhow::Result<Vec<(String, Environment)>> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("reading environment directory at {}", dir.display()))?;
+
+    let mut profiles = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        profiles.push((name, load_environment(&path)?));
+    }
+    profiles.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(profiles)
+}
+
+/// Load an assertions file mapping profile names to their expected mode.
+pub fn load_assertions(path: &Path) -> anyhow::Result<Assertions> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading assertions at {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("parsing assertions at {}", path.display()))
+}
+
+pub fn plan_matrix(manifest: &Manifest, profiles: &[(String, Environment)]) -> Vec<MatrixRow> {
+    profiles
+        .iter()
+        .map(|(profile, env)| {
+            let (chosen_mode, reasons) = match plan_install(manifest, env) {
+                Ok(plan) => (Some(plan.chosen_mode), Vec::new()),
+                Err(PlannerError::NoCompatibleMode { reasons, .. }) => (None, reasons),
+                Err(err) => (None, vec![err.to_string()]),
+            };
+            MatrixRow {
+                profile: profile.clone(),
+                os: env.os.clone(),
+                os_version: env.os_version.clone(),
+                cpu_arch: env.cpu_arch.clone(),
+                ram_gb: env.ram_gb,
+                chosen_mode,
+                reasons,
+            }
+        })
+        .collect()
+}
+
+/// Compare the matrix against the expected modes. Profiles without an
+/// assertion are not checked; asserted profiles missing from the matrix fail.
+pub fn check_assertions(rows: &[MatrixRow], assertions: &Assertions) -> Vec<AssertionFailure> {
+    let describe = |mode: &Option<String>| match mode {
+        Some(mode) => format!("'{mode}'"),
+        None => "no compatible mode".to_string(),
+    };
+
+    assertions
+        .iter()
+        .filter_map(|(profile, expected)| {
+            let Some(row) = rows.iter().find(|row| &row.profile == profile) else {
+                return Some(AssertionFailure {
+                    profile: profile.clone(),
+                    expected: expected.clone(),
+                    actual: None,
+                    message: format!("{profile}: no environment profile named '{profile}'"),
+                });
+            };
+            (&row.chosen_mode != expected).then(|| AssertionFailure {
+                profile: profile.clone(),
+                expected: expected.clone(),
+                actual: row.chosen_mode.clone(),
+                message: format!(
+                    "{profile}: expected {}, got {}",
+                    describe(expected),
+                    describe(&row.chosen_mode)
+                ),
+            })
+        })
+        .collect()
+}
+
+pub fn render_report(rows: &[MatrixRow], format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Csv => render_csv(rows),
+        ReportFormat::Json => serde_json::to_string_pretty(rows).unwrap_or_default(),
+        ReportFormat::Markdown => render_markdown(rows),
+    }
+}
+
+const COLUMNS: [&str; 7] = [
+    "profile",
+    "os",
+    "os_version",
+    "cpu_arch",
+    "ram_gb",
+    "chosen_mode",
+    "reasons",
+];
+
+fn row_cells(row: &MatrixRow) -> [String; 7] {
+    [
+        row.profile.clone(),
+        row.os.clone(),
+        row.os_version.clone(),
+        row.cpu_arch.clone(),
+        row.ram_gb.to_string(),
+        row.chosen_mode.clone().unwrap_or_default(),
+        row.reasons.join("; "),
+    ]
+}
+
+fn render_csv(rows: &[MatrixRow]) -> String {
+    let escape = |cell: &str| {
+        if cell.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        } else {
+            cell.to_string()
+        }
+    };
+
+    let mut out = COLUMNS.join(",");
+    out.push('\n');
+    for row in rows {
+        let cells: Vec<String> = row_cells(row).iter().map(|cell| escape(cell)).collect();
+        out.push_str(&cells.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn render_markdown(rows: &[MatrixRow]) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|").replace('\n', " ");
+
+    let mut out = format!("| {} |\n", COLUMNS.join(" | "));
+    out.push_str(&format!("|{}\n", " --- |".repeat(COLUMNS.len())));
+    for row in rows {
+        let mut cells = row_cells(row);
+        if row.chosen_mode.is_none() {
+            cells[5] = "_none_".to_string();
+        }
+        let cells: Vec<String> = cells.iter().map(|cell| escape(cell)).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{
+        MatrixRow, ReportFormat, check_assertions, load_profiles, plan_matrix, render_report,
+    };
+    use crate::env_detect::Environment;
+    use crate::manifest::{Manifest, Mode, Requirements, Step};
+
+    fn env(os: &str, ram_gb: u64) -> Environment {
+        Environment {
+            os: os.into(),
+            os_version: "10.0".into(),
+            os_version_display: "Windows 10 Pro".into(),
+            cpu_arch: "x64".into(),
+            ram_gb,
+            cpu_cores: Some(4),
+            cpu_features: vec![],
+            disk_free_gb: Some(100),
+            disk_free_gb_by_mode: Default::default(),
+            pkg_managers: vec![],
+            commands: BTreeMap::new(),
+            distro_id: None,
+            distro_version: None,
+            id_like: vec![],
+            libc: None,
+            libc_version: None,
+            fingerprint: None,
+        }
+    }
+
+    fn manifest() -> Manifest {
+        let mode = |ram_gb| Mode {
+            requirements: Some(Requirements {
+                ram_gb: Some(ram_gb),
+                ..Default::default()
+            }),
+            steps: BTreeMap::from([(
+                "windows".to_string(),
+                vec![Step::Run {
+                    run: "echo hi".into(),
+                }],
+            )]),
+            ..Default::default()
+        };
+
+        Manifest {
+            name: "demo".into(),
+            version: "1.0.0".into(),
+            modes: BTreeMap::from([
+                ("full".to_string(), mode(8)),
+                ("light".to_string(), mode(4)),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn rows() -> Vec<MatrixRow> {
+        let profiles = vec![
+            ("laptop".to_string(), env("windows", 4)),
+            ("mac".to_string(), env("macos", 32)),
+            ("workstation".to_string(), env("windows", 32)),
+        ];
+        plan_matrix(&manifest(), &profiles)
+    }
+
+    #[test]
+    fn plans_each_profile() {
+        let rows = rows();
+        assert_eq!(rows[0].chosen_mode.as_deref(), Some("light"));
+        assert_eq!(rows[1].chosen_mode, None);
+        assert!(rows[1].reasons[0].contains("missing required steps"));
+        assert_eq!(rows[2].chosen_mode.as_deref(), Some("full"));
+    }
+
+    #[test]
+    fn reports_changed_and_missing_profiles() {
+        let assertions = BTreeMap::from([
+            ("laptop".to_string(), Some("light".to_string())),
+            ("mac".to_string(), Some("light".to_string())),
+            ("workstation".to_string(), Some("full".to_string())),
+            ("server".to_string(), None),
+        ]);
+
+        let failures = check_assertions(&rows(), &assertions);
+        let messages: Vec<&str> = failures.iter().map(|f| f.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "mac: expected 'light', got no compatible mode",
+                "server: no environment profile named 'server'",
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_csv_with_quoting() {
+        let mut rows = rows();
+        rows[2].os_version = "10.0 \"22H2\", build 19045".into();
+        let csv = render_report(&rows, ReportFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "profile,os,os_version,cpu_arch,ram_gb,chosen_mode,reasons"
+        );
+        assert_eq!(lines[1], "laptop,windows,10.0,x64,4,light,");
+        assert!(lines[2].starts_with("mac,macos,10.0,x64,32,,full: missing"));
+        assert_eq!(
+            lines[3],
+            "workstation,windows,\"10.0 \"\"22H2\"\", build 19045\",x64,32,full,"
+        );
+    }
+
+    #[test]
+    fn renders_markdown_table() {
+        let markdown = render_report(&rows(), ReportFormat::Markdown);
+        let lines: Vec<&str> = markdown.lines().collect();
+        assert_eq!(lines[1], "| --- | --- | --- | --- | --- | --- | --- |");
+        assert_eq!(lines[2], "| laptop | windows | 10.0 | x64 | 4 | light |  |");
+        assert!(lines[3].contains("| _none_ |"));
+    }
+
+    #[test]
+    fn loads_json_profiles_sorted_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, env) in [("b-laptop", env("windows", 4)), ("a-mac", env("macos", 16))] {
+            let path = dir.path().join(format!("{name}.json"));
+            std::fs::write(path, serde_json::to_string(&env).unwrap()).unwrap();
+        }
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let profiles = load_profiles(dir.path()).expect("profiles load");
+        let names: Vec<&str> = profiles.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["a-mac", "b-laptop"]);
+    }
+}
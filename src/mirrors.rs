@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// URL prefix rewrites pointing downloads at internal mirrors, e.g.
+/// `{ "mirrors": { "https://cdn.example.com/": "https://mirror.corp.local/cdn/" } }`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    #[serde(default)]
+    pub mirrors: BTreeMap<String, String>,
+}
+
+impl MirrorConfig {
+    /// `url` with its longest matching prefix replaced by that prefix's
+    /// mirror, or `None` when no prefix matches.
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        self.mirrors
+            .iter()
+            .filter(|(prefix, _)| !prefix.is_empty() && url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, mirror)| format!("{mirror}{}", &url[prefix.len()..]))
+    }
+
+    /// The URLs to try for a download, in order: each source's mirror (when
+    /// one is configured) before the source itself, without duplicates.
+    pub fn candidates(&self, sources: &[&str]) -> Vec<String> {
+        let mut candidates: Vec<String> = Vec::new();
+        for source in sources {
+            for url in self.rewrite(source).into_iter().chain([source.to_string()]) {
+                if !candidates.contains(&url) {
+                    candidates.push(url);
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Where `install` looks for mirror rewrites when `--mirrors` is not given.
+pub fn default_mirror_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("enzyme-installer").join("mirrors.json"))
+}
+
+pub fn load_mirror_config(path: &Path) -> anyhow::Result<MirrorConfig> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading mirror config at {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("parsing mirror config at {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{MirrorConfig, load_mirror_config};
+
+    fn config() -> MirrorConfig {
+        MirrorConfig {
+            mirrors: BTreeMap::from([
+                (
+                    "https://cdn.example.com/".to_string(),
+                    "https://mirror.corp.local/cdn/".to_string(),
+                ),
+                (
+                    "https://cdn.example.com/models/".to_string(),
+                    "https://models.corp.local/".to_string(),
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn rewrites_the_longest_matching_prefix() {
+        let config = config();
+        assert_eq!(
+            config.rewrite("https://cdn.example.com/app/1.0/app.zip"),
+            Some("https://mirror.corp.local/cdn/app/1.0/app.zip".to_string())
+        );
+        assert_eq!(
+            config.rewrite("https://cdn.example.com/models/llm.bin"),
+            Some("https://models.corp.local/llm.bin".to_string())
+        );
+        assert_eq!(config.rewrite("https://github.com/org/app.zip"), None);
+    }
+
+    #[test]
+    fn tries_mirrors_before_each_source() {
+        let candidates = config().candidates(&[
+            "https://cdn.example.com/app.zip",
+            "https://github.com/org/app.zip",
+            "https://cdn.example.com/app.zip",
+        ]);
+        assert_eq!(
+            candidates,
+            vec![
+                "https://mirror.corp.local/cdn/app.zip",
+                "https://cdn.example.com/app.zip",
+                "https://github.com/org/app.zip",
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_mirror_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrors.json");
+        std::fs::write(
+            &path,
+            r#"{ "mirrors": { "https://cdn.example.com/": "https://mirror.corp.local/" } }"#,
+        )
+        .unwrap();
+        let config = load_mirror_config(&path).expect("config should load");
+        assert_eq!(config.mirrors.len(), 1);
+
+        std::fs::write(&path, r#"{ "mirror": {} }"#).unwrap();
+        let err = load_mirror_config(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown field `mirror`"));
+    }
+}
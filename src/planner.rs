@@ -1,22 +1,49 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use serde::Serialize;
 use thiserror::Error;
 
-use crate::env_detect::Environment;
-use crate::manifest::{Manifest, Mode, RuntimeEnv, Step};
+use crate::env_detect::{Environment, normalize_cpu_feature};
+use crate::manifest::{
+    LibcConstraint, Manifest, Mode, Requirements, RuntimeEnv, Step, VersionComparator, VersionOp,
+};
+use crate::vars::{
+    Vars, VarsError, apply_layer, builtin_vars, interpolate, interpolate_step, resolve_references,
+};
 
 #[derive(Debug, Serialize, Clone)]
 pub struct InstallPlan {
     pub app_name: String,
     pub app_version: String,
     pub chosen_mode: String,
+    /// Why `chosen_mode` won over the other compatible modes.
+    pub selection_reason: String,
+    /// Compatible modes, best first.
+    pub compatible_modes: Vec<String>,
     pub os: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub runtime_env: Option<RuntimeEnv>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Variables after layering built-ins, manifest, mode and CLI values.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: Vars,
     pub steps: Vec<PlannedStep>,
 }
 
+/// Caller-supplied knobs for [`plan_install_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct PlanOptions {
+    /// Variable overrides (e.g. from `--set key=value`), applied last.
+    pub vars: BTreeMap<String, String>,
+    /// Use this mode instead of ranking the compatible ones.
+    pub mode: Option<String>,
+    /// Plan the forced `mode` even when its requirements are not met.
+    pub force: bool,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct PlannedStep {
     pub description: String,
@@ -31,17 +58,38 @@ pub struct PlannedStep {
 pub enum PlannerError {
     #[error("no compatible mode found for environment {environment:?}: {reasons:?}")]
     NoCompatibleMode {
-        environment: Environment,
+        environment: Box<Environment>,
+        reasons: Vec<String>,
+    },
+    #[error("mode '{0}' is not defined in the manifest")]
+    UnknownMode(String),
+    #[error("mode '{mode}' is not compatible with this environment: {}", .reasons.join("; "))]
+    IncompatibleMode {
+        mode: String,
+        environment: Box<Environment>,
         reasons: Vec<String>,
+        /// Whether `force` could override the failure (it cannot when the
+        /// mode has no steps for this platform).
+        forceable: bool,
     },
+    #[error(transparent)]
+    Vars(#[from] VarsError),
 }
 
 pub fn plan_install(manifest: &Manifest, env: &Environment) -> Result<InstallPlan, PlannerError> {
+    plan_install_with_options(manifest, env, &PlanOptions::default())
+}
+
+pub fn plan_install_with_options(
+    manifest: &Manifest,
+    env: &Environment,
+    options: &PlanOptions,
+) -> Result<InstallPlan, PlannerError> {
     let mut compatible_modes: Vec<(&String, &Mode)> = Vec::new();
     let mut reasons = Vec::new();
 
     for (mode_name, mode) in manifest.modes.iter() {
-        match is_mode_compatible(mode_name, mode, env) {
+        match is_mode_compatible(mode, &env.for_mode(mode_name)) {
             Ok(true) => compatible_modes.push((mode_name, mode)),
             Ok(false) => reasons.push(format!(
                 "{mode_name}: missing required steps for {}",
@@ -51,14 +99,25 @@ pub fn plan_install(manifest: &Manifest, env: &Environment) -> Result<InstallPla
         }
     }
 
-    if compatible_modes.is_empty() {
+    let ranked = rank_modes(manifest, env, &compatible_modes);
+    let mut warnings = Vec::new();
+    let (chosen, selection_reason) = if let Some(forced) = &options.mode {
+        let chosen = force_mode(manifest, forced, env, options.force, &mut warnings)?;
+        (chosen, format!("'{forced}' was requested with --mode"))
+    } else if ranked.is_empty() {
         return Err(PlannerError::NoCompatibleMode {
-            environment: env.clone(),
+            environment: Box::new(env.clone()),
             reasons,
         });
-    }
-
-    let chosen = choose_best_mode(&compatible_modes);
+    } else {
+        (ranked[0], selection_reason(manifest, env, &ranked))
+    };
+    warnings.extend(
+        unmet_recommendations(chosen.1, &env.for_mode(chosen.0))
+            .into_iter()
+            .map(|reason| format!("mode '{}' recommendation not met: {reason}", chosen.0)),
+    );
+    let vars = resolve_vars(manifest, chosen.0, chosen.1, env, options)?;
     let steps = chosen
         .1
         .steps
@@ -66,89 +125,809 @@ pub fn plan_install(manifest: &Manifest, env: &Environment) -> Result<InstallPla
         .expect("validated in compatibility check")
         .iter()
         .enumerate()
-        .map(|(idx, step)| PlannedStep {
-            description: step.description(),
-            command: step.command(),
-            step: step.clone(),
-            index: idx,
+        .map(|(idx, step)| {
+            let step = interpolate_step(step, &vars);
+            PlannedStep {
+                description: step.description(),
+                command: step.command(),
+                step,
+                index: idx,
+            }
         })
         .collect();
+    let runtime_env = chosen.1.runtime_env.clone().map(|mut runtime| {
+        runtime.root = interpolate(&runtime.root.to_string_lossy(), &vars).into();
+        runtime
+    });
 
     Ok(InstallPlan {
         app_name: manifest.name.clone(),
         app_version: manifest.version.clone(),
         chosen_mode: chosen.0.clone(),
+        selection_reason,
+        compatible_modes: ranked.iter().map(|(name, _)| name.to_string()).collect(),
         os: env.os.clone(),
-        runtime_env: chosen.1.runtime_env.clone(),
+        runtime_env,
+        warnings,
+        vars,
         steps,
     })
 }
 
-fn is_mode_compatible(mode_name: &str, mode: &Mode, env: &Environment) -> Result<bool, String> {
-    if !mode.steps.contains_key(&env.os) {
-        return Ok(false);
-    }
+/// Look up a mode requested by name. Unmet requirements are an error unless
+/// `force` is set, in which case they become plan warnings; a mode without
+/// steps for this platform can never be forced.
+fn force_mode<'a>(
+    manifest: &'a Manifest,
+    name: &str,
+    env: &Environment,
+    force: bool,
+    warnings: &mut Vec<String>,
+) -> Result<(&'a String, &'a Mode), PlannerError> {
+    let (name, mode) = manifest
+        .modes
+        .get_key_value(name)
+        .ok_or_else(|| PlannerError::UnknownMode(name.to_string()))?;
+    let env = env.for_mode(name);
 
-    if let Some(requirements) = &mode.requirements {
-        if !requirements.os.is_empty()
-            && !requirements
-                .os
-                .iter()
-                .any(|constraint| os_matches(env, constraint))
-        {
-            return Err(format!(
-                "requires {:?}, found {} {}",
-                requirements.os, env.os, env.os_version
+    let incompatible = |reason: String, forceable: bool| PlannerError::IncompatibleMode {
+        mode: name.clone(),
+        environment: Box::new(env.clone().into_owned()),
+        reasons: vec![reason],
+        forceable,
+    };
+    match is_mode_compatible(mode, &env) {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(incompatible(
+                format!("missing required steps for {}", env.os),
+                false,
             ));
         }
+        Err(reason) if force => warnings.push(format!(
+            "mode '{name}' was forced although it is not compatible: {reason}"
+        )),
+        Err(reason) => return Err(incompatible(reason, true)),
+    }
+
+    Ok((name, mode))
+}
+
+/// Built-in facts first, then manifest vars, mode vars and CLI overrides;
+/// each later layer wins and may reference names from earlier ones.
+fn resolve_vars(
+    manifest: &Manifest,
+    mode_name: &str,
+    mode: &Mode,
+    env: &Environment,
+    options: &PlanOptions,
+) -> Result<Vars, VarsError> {
+    let mut vars = builtin_vars(manifest, mode_name, env);
+    apply_layer(&mut vars, &manifest.vars);
+    apply_layer(&mut vars, &mode.vars);
+    apply_layer(&mut vars, &options.vars);
+    resolve_references(&vars)
+}
+
+/// The directory mode `name` installs into, where its `disk_free_gb` is
+/// measured: the deepest directory holding every step destination and the
+/// runtime root of the mode's steps for this OS, with relative paths resolved
+/// against the working directory. Falls back to the working directory.
+pub fn install_target(
+    manifest: &Manifest,
+    name: &str,
+    env: &Environment,
+    options: &PlanOptions,
+) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let Some(mode) = manifest.modes.get(name) else {
+        return cwd;
+    };
+    let (Some(steps), Ok(vars)) = (
+        mode.steps.get(&env.os),
+        resolve_vars(manifest, name, mode, env, options),
+    ) else {
+        return cwd;
+    };
+    let files = steps.iter().map(|step| interpolate_step(step, &vars));
+    let mut dirs: Vec<PathBuf> = files
+        .filter_map(|step| match step {
+            Step::Download { download } => download.dest.parent().map(Path::to_path_buf),
+            Step::Extract { extract } => Some(extract.dest),
+            Step::TemplateConfig { template_config } => {
+                template_config.dest.parent().map(Path::to_path_buf)
+            }
+            Step::Run { .. } => None,
+        })
+        .collect();
+    if let Some(runtime) = &mode.runtime_env {
+        dirs.push(interpolate(&runtime.root.to_string_lossy(), &vars).into());
+    }
+    dirs.into_iter()
+        .map(|dir| cwd.join(dir))
+        .reduce(|target, dir| common_ancestor(&target, &dir))
+        .unwrap_or(cwd)
+}
+
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x)
+        .collect()
+}
+
+/// Outcome of checking one requirement of a mode against the environment.
+/// Several checks sharing a `requirement` are alternatives (e.g. the entries
+/// of `requirements.os`): the requirement is met when any of them passes.
+#[derive(Debug, Serialize, Clone)]
+pub struct RequirementCheck {
+    pub requirement: String,
+    pub expected: String,
+    pub actual: String,
+    pub passed: bool,
+    /// What the check's result means for the mode once `all`/`any`/`not`
+    /// are taken into account; set by [`check_requirements`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<CheckOutcome>,
+}
+
+/// The effect of one check on its mode.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckOutcome {
+    /// The check holds as the mode needs it to: it passed, or it failed
+    /// inside a `not` set and so keeps that set unmet.
+    Pass,
+    /// The check failed and makes the mode incompatible.
+    Fail,
+    /// The check matched inside a `not` set that is met, which makes the mode
+    /// incompatible.
+    Excluded,
+    /// A `recommended` check that failed, or matched a met `not` set; it only
+    /// lowers the mode's ranking.
+    Warn,
+    /// The result does not matter: another entry for the same requirement
+    /// passed, another `any` alternative holds, or the enclosing `not` set is
+    /// unmet for another reason.
+    NotNeeded,
+}
+
+/// How the result of a requirement set affects its mode.
+#[derive(Clone, Copy)]
+struct CheckContext {
+    /// Whether the set's result decides anything.
+    needed: bool,
+    /// Inside an odd number of `not` sets, so the mode needs the set to fail.
+    negated: bool,
+    /// Part of `recommended`, so failures are warnings.
+    recommended: bool,
+}
+
+impl CheckContext {
+    /// The outcome of one predicate check of a set whose overall result is
+    /// `holds`; `group_passed` tells whether any check for the same
+    /// requirement passed.
+    fn outcome(self, holds: bool, passed: bool, group_passed: bool) -> CheckOutcome {
+        let failure = if self.recommended {
+            CheckOutcome::Warn
+        } else {
+            CheckOutcome::Fail
+        };
+        let excluded = if self.recommended {
+            CheckOutcome::Warn
+        } else {
+            CheckOutcome::Excluded
+        };
+        match (self.needed, self.negated) {
+            (false, _) => CheckOutcome::NotNeeded,
+            (true, false) if passed => CheckOutcome::Pass,
+            (true, false) if group_passed => CheckOutcome::NotNeeded,
+            (true, false) => failure,
+            (true, true) if holds && passed => excluded,
+            (true, true) if !holds && !group_passed => CheckOutcome::Pass,
+            (true, true) => CheckOutcome::NotNeeded,
+        }
+    }
+
+    /// The context of a part of a set whose overall result is `holds`. When a
+    /// negated set is already unmet, the parts that hold cannot change that.
+    fn part(self, holds: bool, part_satisfied: bool) -> CheckContext {
+        CheckContext {
+            needed: self.needed && !(self.negated && !holds && part_satisfied),
+            ..self
+        }
+    }
+}
 
-        if !requirements.cpu_arch.is_empty()
-            && !requirements
+/// Per-mode compatibility report produced by [`explain_modes`].
+#[derive(Debug, Serialize, Clone)]
+pub struct ModeExplanation {
+    pub name: String,
+    pub compatible: bool,
+    pub chosen: bool,
+    /// The first failing requirement or sub-expression of an incompatible mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub checks: Vec<RequirementCheck>,
+    /// Unmet `recommended` requirements; these never make a mode incompatible.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Explanation {
+    pub modes: Vec<ModeExplanation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chosen_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection_reason: Option<String>,
+}
+
+/// Evaluate every requirement of every mode, without stopping at the first
+/// failure, and report which mode planning would choose.
+pub fn explain_modes(manifest: &Manifest, env: &Environment) -> Explanation {
+    let mut compatible_modes = Vec::new();
+    let mut modes = Vec::new();
+    for (name, mode) in manifest.modes.iter() {
+        let env = env.for_mode(name);
+        let reason = incompatibility(mode, &env);
+        if reason.is_none() {
+            compatible_modes.push((name, mode));
+        }
+        modes.push(ModeExplanation {
+            name: name.clone(),
+            compatible: reason.is_none(),
+            chosen: false,
+            reason,
+            checks: check_requirements(mode, &env),
+            warnings: unmet_recommendations(mode, &env),
+        });
+    }
+
+    let ranked = rank_modes(manifest, env, &compatible_modes);
+    let chosen_mode = ranked.first().map(|(name, _)| name.to_string());
+    for mode in &mut modes {
+        mode.chosen = chosen_mode.as_ref() == Some(&mode.name);
+    }
+
+    Explanation {
+        modes,
+        selection_reason: chosen_mode
+            .is_some()
+            .then(|| selection_reason(manifest, env, &ranked)),
+        chosen_mode,
+    }
+}
+
+/// Check each requirement of `mode` against `env`, starting with the
+/// presence of steps for the current platform. Checks from nested
+/// `all`/`any`/`not` sets are labelled with their path, e.g. `any[1].ram_gb`,
+/// and checks of the `recommended` set are prefixed with `recommended.`.
+pub fn check_requirements(mode: &Mode, env: &Environment) -> Vec<RequirementCheck> {
+    let has_steps = mode.steps.contains_key(&env.os);
+    let mut checks = vec![RequirementCheck {
+        requirement: "platform_steps".to_string(),
+        expected: env.os.clone(),
+        actual: if mode.steps.is_empty() {
+            "none".to_string()
+        } else {
+            mode.steps.keys().cloned().collect::<Vec<_>>().join(", ")
+        },
+        passed: has_steps,
+        outcome: Some(if has_steps {
+            CheckOutcome::Pass
+        } else {
+            CheckOutcome::Fail
+        }),
+    }];
+
+    let context = CheckContext {
+        needed: true,
+        negated: false,
+        recommended: false,
+    };
+    if let Some(requirements) = &mode.requirements {
+        collect_checks(requirements, env, "", context, &mut checks);
+    }
+    if let Some(recommended) = &mode.recommended {
+        let context = CheckContext {
+            recommended: true,
+            ..context
+        };
+        collect_checks(recommended, env, "recommended", context, &mut checks);
+    }
+    checks
+}
+
+fn collect_checks(
+    requirements: &Requirements,
+    env: &Environment,
+    path: &str,
+    context: CheckContext,
+    checks: &mut Vec<RequirementCheck>,
+) {
+    let holds = unmet_requirements(requirements, env, path).is_none();
+    let predicates = predicate_checks(requirements, env, path);
+    for check in &predicates {
+        let group_passed = predicates
+            .iter()
+            .any(|other| other.requirement == check.requirement && other.passed);
+        checks.push(RequirementCheck {
+            outcome: Some(context.outcome(holds, check.passed, group_passed)),
+            ..check.clone()
+        });
+    }
+
+    for (idx, child) in requirements.all.iter().enumerate() {
+        let child_path = nested_path(path, &format!("all[{idx}]"));
+        let child_holds = unmet_requirements(child, env, &child_path).is_none();
+        let child_context = context.part(holds, child_holds);
+        collect_checks(child, env, &child_path, child_context, checks);
+    }
+
+    let branches: Vec<(String, bool)> = (0..requirements.any.len())
+        .map(|idx| {
+            let child_path = nested_path(path, &format!("any[{idx}]"));
+            let child_holds =
+                unmet_requirements(&requirements.any[idx], env, &child_path).is_none();
+            (child_path, child_holds)
+        })
+        .collect();
+    let any_holds = branches.iter().any(|(_, child_holds)| *child_holds);
+    for (child, (child_path, child_holds)) in requirements.any.iter().zip(&branches) {
+        // Once one alternative holds, the others do not matter.
+        let mut child_context = context.part(holds, any_holds);
+        child_context.needed &= !any_holds || *child_holds;
+        collect_checks(child, env, child_path, child_context, checks);
+    }
+
+    if let Some(child) = &requirements.not {
+        let child_path = nested_path(path, "not");
+        let child_holds = unmet_requirements(child, env, &child_path).is_none();
+        let child_context = CheckContext {
+            negated: !context.negated,
+            ..context.part(holds, !child_holds)
+        };
+        collect_checks(child, env, &child_path, child_context, checks);
+    }
+}
+
+fn nested_path(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{path}.{segment}")
+    }
+}
+
+/// Checks for the predicates set directly on `requirements`, ignoring its
+/// nested sets.
+fn predicate_checks(
+    requirements: &Requirements,
+    env: &Environment,
+    path: &str,
+) -> Vec<RequirementCheck> {
+    let mut checks = Vec::new();
+
+    for constraint in &requirements.os {
+        checks.push(RequirementCheck {
+            requirement: "os".to_string(),
+            expected: constraint.to_string(),
+            actual: describe_os(env),
+            passed: os_matches(env, constraint),
+            outcome: None,
+        });
+    }
+
+    if !requirements.cpu_arch.is_empty() {
+        checks.push(RequirementCheck {
+            requirement: "cpu_arch".to_string(),
+            expected: requirements.cpu_arch.join(" or "),
+            actual: env.cpu_arch.clone(),
+            passed: requirements
                 .cpu_arch
                 .iter()
-                .any(|arch| arch.eq_ignore_ascii_case(&env.cpu_arch))
+                .any(|arch| arch.eq_ignore_ascii_case(&env.cpu_arch)),
+            outcome: None,
+        });
+    }
+
+    if let Some(required_ram) = requirements.ram_gb {
+        checks.push(RequirementCheck {
+            requirement: "ram_gb".to_string(),
+            expected: format!(">= {required_ram} GiB"),
+            actual: format!("{} GiB", env.ram_gb),
+            passed: env.ram_gb >= required_ram,
+            outcome: None,
+        });
+    }
+
+    if let Some(required_disk) = requirements.disk_free_gb {
+        checks.push(RequirementCheck {
+            requirement: "disk_free_gb".to_string(),
+            expected: format!(">= {required_disk} GiB free"),
+            actual: env
+                .disk_free_gb
+                .map_or_else(|| "unknown".to_string(), |free| format!("{free} GiB free")),
+            passed: env.disk_free_gb.is_some_and(|free| free >= required_disk),
+            outcome: None,
+        });
+    }
+
+    if let Some(required_cores) = requirements.cpu_cores {
+        checks.push(RequirementCheck {
+            requirement: "cpu_cores".to_string(),
+            expected: format!(">= {required_cores} cores"),
+            actual: env
+                .cpu_cores
+                .map_or_else(|| "unknown".to_string(), |cores| format!("{cores} cores")),
+            passed: env.cpu_cores.is_some_and(|cores| cores >= required_cores),
+            outcome: None,
+        });
+    }
+
+    if !requirements.cpu_features.is_empty() {
+        let required: Vec<String> = requirements
+            .cpu_features
+            .iter()
+            .map(|feature| normalize_cpu_feature(feature))
+            .collect();
+        let (present, missing): (Vec<&str>, Vec<&str>) = required
+            .iter()
+            .map(String::as_str)
+            .partition(|feature| env.cpu_features.iter().any(|have| have == feature));
+        let present = if present.is_empty() {
+            "none".to_string()
+        } else {
+            present.join(", ")
+        };
+        checks.push(RequirementCheck {
+            requirement: "cpu_features".to_string(),
+            expected: required.join(", "),
+            actual: if missing.is_empty() {
+                present
+            } else {
+                format!("{present} (missing {})", missing.join(", "))
+            },
+            passed: missing.is_empty(),
+            outcome: None,
+        });
+    }
+
+    for command in &requirements.commands {
+        let found = env.commands.get(&command.name);
+        checks.push(RequirementCheck {
+            requirement: format!("command:{}", command.name),
+            expected: if command.comparators.is_empty() {
+                "on PATH".to_string()
+            } else {
+                command.to_string()
+            },
+            actual: match found {
+                Some(version) => format!("{} {version}", command.name),
+                None => "none".to_string(),
+            },
+            passed: found.is_some_and(|version| version_meets(&command.comparators, version)),
+            outcome: None,
+        });
+    }
+
+    for constraint in &requirements.libc {
+        checks.push(RequirementCheck {
+            requirement: "libc".to_string(),
+            expected: constraint.to_string(),
+            actual: match (&env.libc, &env.libc_version) {
+                (Some(family), Some(version)) => format!("{family} {version}"),
+                (Some(family), None) => family.clone(),
+                (None, _) => "unknown".to_string(),
+            },
+            passed: libc_matches(env, constraint),
+            outcome: None,
+        });
+    }
+
+    if !requirements.pkg_managers.is_empty() {
+        checks.push(RequirementCheck {
+            requirement: "pkg_managers".to_string(),
+            expected: requirements.pkg_managers.join(" or "),
+            actual: if env.pkg_managers.is_empty() {
+                "none".to_string()
+            } else {
+                env.pkg_managers.join(", ")
+            },
+            passed: requirements.pkg_managers.iter().any(|manager| {
+                env.pkg_managers
+                    .iter()
+                    .any(|found| found.eq_ignore_ascii_case(manager))
+            }),
+            outcome: None,
+        });
+    }
+
+    for check in &mut checks {
+        check.requirement = nested_path(path, &check.requirement);
+    }
+    checks
+}
+
+/// Describe the first requirement none of whose checks passed.
+fn unmet_requirement(checks: &[RequirementCheck]) -> Option<String> {
+    unmet_groups(checks).into_iter().next()
+}
+
+/// Describe every requirement none of whose checks passed, in check order.
+fn unmet_groups(checks: &[RequirementCheck]) -> Vec<String> {
+    let mut reported: Vec<&str> = Vec::new();
+    let mut reasons = Vec::new();
+    for failed in checks {
+        let group = || {
+            checks
+                .iter()
+                .filter(|other| other.requirement == failed.requirement)
+        };
+        if failed.passed
+            || reported.contains(&failed.requirement.as_str())
+            || group().any(|other| other.passed)
         {
-            return Err(format!(
-                "requires CPU in {:?}, found {}",
-                requirements.cpu_arch, env.cpu_arch
-            ));
+            continue;
         }
+        reported.push(&failed.requirement);
+        let expected: Vec<&str> = group().map(|other| other.expected.as_str()).collect();
+        reasons.push(format!(
+            "requires {} {}, found {}",
+            failed.requirement,
+            expected.join(" or "),
+            failed.actual
+        ));
+    }
+    reasons
+}
 
-        if let Some(required_ram) = requirements.ram_gb {
-            if env.ram_gb < required_ram {
-                return Err(format!(
-                    "requires >= {required_ram} GiB RAM, found {} GiB",
-                    env.ram_gb
-                ));
+/// Describe each unmet entry of the mode's `recommended` set: every failing
+/// top-level predicate, every failing `all` set, and a failing `any` or `not`.
+fn unmet_recommendations(mode: &Mode, env: &Environment) -> Vec<String> {
+    let Some(recommended) = &mode.recommended else {
+        return Vec::new();
+    };
+
+    let mut reasons = unmet_groups(&predicate_checks(recommended, env, ""));
+    for (idx, child) in recommended.all.iter().enumerate() {
+        reasons.extend(unmet_requirements(child, env, &format!("all[{idx}]")));
+    }
+    let any = Requirements {
+        any: recommended.any.clone(),
+        ..Default::default()
+    };
+    reasons.extend(unmet_requirements(&any, env, ""));
+    let not = Requirements {
+        not: recommended.not.clone(),
+        ..Default::default()
+    };
+    reasons.extend(unmet_requirements(&not, env, ""));
+    reasons
+}
+
+/// Evaluate `requirements` as a boolean expression: its own predicates and
+/// `all` sets must hold, at least one `any` set must hold, and the `not` set
+/// must not. Returns a description of the first failing sub-expression.
+fn unmet_requirements(
+    requirements: &Requirements,
+    env: &Environment,
+    path: &str,
+) -> Option<String> {
+    if let Some(reason) = unmet_requirement(&predicate_checks(requirements, env, path)) {
+        return Some(reason);
+    }
+
+    for (idx, child) in requirements.all.iter().enumerate() {
+        let child_path = nested_path(path, &format!("all[{idx}]"));
+        if let Some(reason) = unmet_requirements(child, env, &child_path) {
+            return Some(reason);
+        }
+    }
+
+    if !requirements.any.is_empty() {
+        let mut reasons = Vec::new();
+        for (idx, child) in requirements.any.iter().enumerate() {
+            let child_path = nested_path(path, &format!("any[{idx}]"));
+            match unmet_requirements(child, env, &child_path) {
+                Some(reason) => reasons.push(reason),
+                None => {
+                    reasons.clear();
+                    break;
+                }
             }
         }
+        if !reasons.is_empty() {
+            return Some(format!(
+                "no alternative in {} is met: {}",
+                nested_path(path, "any"),
+                reasons.join("; ")
+            ));
+        }
     }
 
-    Ok(true)
+    if let Some(child) = &requirements.not {
+        let child_path = nested_path(path, "not");
+        if unmet_requirements(child, env, &child_path).is_none() {
+            let mut checks = Vec::new();
+            let context = CheckContext {
+                needed: true,
+                negated: true,
+                recommended: false,
+            };
+            collect_checks(child, env, &child_path, context, &mut checks);
+            let matched: Vec<String> = checks
+                .iter()
+                .filter(|check| check.passed)
+                .map(|check| {
+                    format!(
+                        "{} {}, found {}",
+                        check.requirement, check.expected, check.actual
+                    )
+                })
+                .collect();
+            return Some(format!("{child_path} is met: {}", matched.join("; ")));
+        }
+    }
+
+    None
 }
 
-fn os_matches(env: &Environment, constraint: &crate::manifest::OsConstraint) -> bool {
-    if constraint.family != env.os {
-        return false;
+/// Why `mode` cannot be used on `env`, or `None` when it can.
+fn incompatibility(mode: &Mode, env: &Environment) -> Option<String> {
+    if !mode.steps.contains_key(&env.os) {
+        return Some(format!("missing required steps for {}", env.os));
+    }
+    mode.requirements
+        .as_ref()
+        .and_then(|requirements| unmet_requirements(requirements, env, ""))
+}
+
+fn is_mode_compatible(mode: &Mode, env: &Environment) -> Result<bool, String> {
+    if !mode.steps.contains_key(&env.os) {
+        return Ok(false);
+    }
+
+    match incompatibility(mode, env) {
+        Some(reason) => Err(reason),
+        None => Ok(true),
+    }
+}
+
+fn describe_os(env: &Environment) -> String {
+    match (&env.distro_id, &env.distro_version) {
+        (Some(id), Some(version)) => format!("{} {} ({id} {version})", env.os, env.os_version),
+        (Some(id), None) => format!("{} {} ({id})", env.os, env.os_version),
+        _ => format!("{} {}", env.os, env.os_version),
     }
+}
 
-    if let Some(min_version) = &constraint.min_version {
-        version_meets(min_version, &env.os_version)
+/// A constraint matches the platform family (compared against `os_version`),
+/// the distribution `ID`, or any `ID_LIKE` entry. Distribution matches compare
+/// against `VERSION_ID`, so `debian>=12` also admits derivatives whose own
+/// version satisfies the bound.
+fn os_matches(env: &Environment, constraint: &crate::manifest::OsConstraint) -> bool {
+    let actual_version = if constraint.family == env.os {
+        Some(env.os_version.as_str())
+    } else if env.distro_id.as_deref() == Some(constraint.family.as_str())
+        || env.id_like.iter().any(|id| id == &constraint.family)
+    {
+        env.distro_version.as_deref()
     } else {
-        true
+        return false;
+    };
+
+    match actual_version {
+        Some(actual) => version_meets(&constraint.comparators, actual),
+        None => constraint.comparators.is_empty(),
     }
 }
 
-fn choose_best_mode(modes: &[(&String, &Mode)]) -> (&String, &Mode) {
-    if let Some(full_mode) = modes.iter().find(|(name, _)| name.as_str() == "full") {
-        return full_mode;
+/// A libc constraint matches the detected family; comparators need a known
+/// version.
+fn libc_matches(env: &Environment, constraint: &LibcConstraint) -> bool {
+    if env.libc.as_deref() != Some(constraint.family.as_str()) {
+        return false;
+    }
+    match &env.libc_version {
+        Some(version) => version_meets(&constraint.comparators, version),
+        None => constraint.comparators.is_empty(),
     }
+}
 
-    modes
+/// Order compatible modes best first. The ranking is, in order:
+/// 1. position in the manifest's `mode_order` (listed modes before unlisted);
+/// 2. `priority`, higher first (unset counts as 0);
+/// 3. number of unmet `recommended` requirements, fewer first;
+/// 4. `ram_gb` requirement, higher first, as the more capable mode;
+/// 5. mode name, alphabetically.
+fn rank_modes<'a>(
+    manifest: &Manifest,
+    env: &Environment,
+    modes: &[(&'a String, &'a Mode)],
+) -> Vec<(&'a String, &'a Mode)> {
+    let mut ranked = modes.to_vec();
+    ranked.sort_by(|a, b| compare_modes(manifest, env, a, b));
+    ranked
+}
+
+fn compare_modes(
+    manifest: &Manifest,
+    env: &Environment,
+    a: &(&String, &Mode),
+    b: &(&String, &Mode),
+) -> Ordering {
+    order_position(manifest, a.0)
+        .cmp(&order_position(manifest, b.0))
+        .then_with(|| priority(b.1).cmp(&priority(a.1)))
+        .then_with(|| recommendation_misses(env, a).cmp(&recommendation_misses(env, b)))
+        .then_with(|| required_ram(b.1).cmp(&required_ram(a.1)))
+        .then_with(|| a.0.cmp(b.0))
+}
+
+fn order_position(manifest: &Manifest, name: &str) -> usize {
+    manifest
+        .mode_order
         .iter()
-        .max_by(|(_, a), (_, b)| required_ram(a).cmp(&required_ram(b)))
-        .copied()
-        .expect("at least one mode available")
+        .position(|entry| entry == name)
+        .unwrap_or(usize::MAX)
+}
+
+fn priority(mode: &Mode) -> i64 {
+    mode.priority.unwrap_or(0)
+}
+
+fn recommendation_misses(env: &Environment, (name, mode): &(&String, &Mode)) -> usize {
+    unmet_recommendations(mode, &env.for_mode(name)).len()
+}
+
+/// Describe which ranking rule separated the winner from the runner-up.
+fn selection_reason(manifest: &Manifest, env: &Environment, ranked: &[(&String, &Mode)]) -> String {
+    let (winner, runner_up) = match ranked {
+        [winner] => return format!("'{}' is the only compatible mode", winner.0),
+        [winner, runner_up, ..] => (winner, runner_up),
+        [] => unreachable!("at least one mode available"),
+    };
+
+    if order_position(manifest, winner.0) != order_position(manifest, runner_up.0) {
+        format!(
+            "'{}' comes before '{}' in mode_order",
+            winner.0, runner_up.0
+        )
+    } else if priority(winner.1) != priority(runner_up.1) {
+        format!(
+            "'{}' has the highest priority ({}, next is '{}' with {})",
+            winner.0,
+            priority(winner.1),
+            runner_up.0,
+            priority(runner_up.1)
+        )
+    } else if recommendation_misses(env, winner) != recommendation_misses(env, runner_up) {
+        format!(
+            "tied with '{}' on priority {}; '{}' misses fewer recommendations ({} vs {})",
+            runner_up.0,
+            priority(winner.1),
+            winner.0,
+            recommendation_misses(env, winner),
+            recommendation_misses(env, runner_up)
+        )
+    } else if required_ram(winner.1) != required_ram(runner_up.1) {
+        format!(
+            "tied with '{}' on priority {}; '{}' requires more RAM ({} GiB vs {} GiB)",
+            runner_up.0,
+            priority(winner.1),
+            winner.0,
+            required_ram(winner.1),
+            required_ram(runner_up.1)
+        )
+    } else {
+        format!(
+            "tied with '{}' on priority and RAM; '{}' sorts first by name",
+            runner_up.0, winner.0
+        )
+    }
 }
 
 fn required_ram(mode: &Mode) -> u64 {
@@ -158,13 +937,44 @@ fn required_ram(mode: &Mode) -> u64 {
         .unwrap_or(0)
 }
 
-fn version_meets(min_version: &str, actual: &str) -> bool {
-    let min_parts: Option<Vec<u64>> = parse_version(min_version);
-    let actual_parts: Option<Vec<u64>> = parse_version(actual);
+/// Whether `actual` satisfies every comparator. Versions compare
+/// numerically by dotted segment; when either side is not numeric only
+/// `==`/`!=` apply, as exact string comparisons.
+fn version_meets(comparators: &[VersionComparator], actual: &str) -> bool {
+    comparators
+        .iter()
+        .all(|comparator| comparator_meets(comparator, actual))
+}
 
-    match (min_parts, actual_parts) {
-        (Some(min), Some(actual)) => compare_versions(&actual, &min) != Ordering::Less,
-        _ => actual == min_version,
+fn comparator_meets(comparator: &VersionComparator, actual: &str) -> bool {
+    if let Some(prefix) = comparator.wildcard_prefix() {
+        let matches = match (parse_version(prefix), parse_version(actual)) {
+            _ if prefix.is_empty() => true,
+            (Some(prefix), Some(actual)) => actual.starts_with(&prefix),
+            _ => actual == prefix || actual.starts_with(&format!("{prefix}.")),
+        };
+        return matches == (comparator.op == VersionOp::Eq);
+    }
+
+    let ordering = match (parse_version(&comparator.version), parse_version(actual)) {
+        (Some(expected), Some(actual)) => compare_versions(&actual, &expected),
+        _ => {
+            let equal = actual == comparator.version;
+            return match comparator.op {
+                VersionOp::Eq => equal,
+                VersionOp::Ne => !equal,
+                _ => false,
+            };
+        }
+    };
+
+    match comparator.op {
+        VersionOp::Lt => ordering == Ordering::Less,
+        VersionOp::Le => ordering != Ordering::Greater,
+        VersionOp::Eq => ordering == Ordering::Equal,
+        VersionOp::Ne => ordering != Ordering::Equal,
+        VersionOp::Ge => ordering != Ordering::Less,
+        VersionOp::Gt => ordering == Ordering::Greater,
     }
 }
 
@@ -193,9 +1003,14 @@ fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
 mod tests {
     use std::collections::BTreeMap;
 
-    use super::{compare_versions, parse_version, plan_install, version_meets};
+    use super::{
+        CheckOutcome, PlanOptions, compare_versions, os_matches, parse_version, plan_install,
+        plan_install_with_options, version_meets,
+    };
     use crate::env_detect::Environment;
-    use crate::manifest::{Manifest, Mode, Requirements, Step, parse_os_constraint};
+    use crate::manifest::{
+        Manifest, Mode, Requirements, Step, parse_command_requirement, parse_os_constraint,
+    };
 
     #[test]
     fn compares_versions_with_padding() {
@@ -206,8 +1021,47 @@ mod tests {
 
     #[test]
     fn version_meets_handles_unparseable_versions() {
-        assert!(!version_meets("abc", "10.0"));
-        assert!(version_meets("10.0", "10"));
+        assert!(!meets("windows>=abc", "10.0"));
+        assert!(meets("windows>=10.0", "10"));
+        assert!(meets("windows==abc", "abc"));
+        assert!(meets("windows!=abc", "10.0"));
+    }
+
+    fn meets(constraint: &str, actual: &str) -> bool {
+        version_meets(
+            &parse_os_constraint(constraint).unwrap().comparators,
+            actual,
+        )
+    }
+
+    #[test]
+    fn version_meets_evaluates_every_operator() {
+        assert!(meets("macos<15", "14.6"));
+        assert!(!meets("macos<15", "15.0"));
+        assert!(meets("macos<=15", "15"));
+        assert!(meets("macos==14", "14.0.0"));
+        assert!(meets("macos!=14.5", "14.6"));
+        assert!(!meets("macos>14", "14.0"));
+        assert!(meets("macos>14", "14.0.1"));
+    }
+
+    #[test]
+    fn version_meets_requires_every_comparator_in_a_range() {
+        assert!(meets("macos>=12,<15", "12.0"));
+        assert!(meets("macos>=12,<15", "14.7.1"));
+        assert!(!meets("macos>=12,<15", "15.0"));
+        assert!(!meets("macos>=12,<15", "11.7"));
+    }
+
+    #[test]
+    fn version_meets_matches_wildcards_by_prefix() {
+        assert!(meets("windows==10.*", "10"));
+        assert!(meets("windows==10.*", "10.0.19045"));
+        assert!(!meets("windows==10.*", "11.0"));
+        assert!(!meets("windows==10.*", "100.1"));
+        assert!(meets("windows!=10.*", "11.0.22631"));
+        assert!(meets("macos==14.2.*", "14.2.1"));
+        assert!(!meets("macos==14.2.*", "14.3"));
     }
 
     #[test]
@@ -219,9 +1073,21 @@ mod tests {
         Environment {
             os: "macos".to_string(),
             os_version: "14.0".to_string(),
+            os_version_display: "macOS 14.0 Sonoma".to_string(),
             cpu_arch: "arm64".to_string(),
             ram_gb: 16,
+            cpu_cores: Some(8),
+            cpu_features: vec!["neon".to_string(), "aes".to_string()],
+            disk_free_gb: Some(200),
+            disk_free_gb_by_mode: Default::default(),
             pkg_managers: vec![],
+            commands: BTreeMap::new(),
+            distro_id: None,
+            distro_version: None,
+            id_like: vec![],
+            libc: None,
+            libc_version: None,
+            fingerprint: None,
         }
     }
 
@@ -234,6 +1100,7 @@ mod tests {
                     os: vec![parse_os_constraint("macos>=13").unwrap()],
                     cpu_arch: vec!["arm64".to_string()],
                     ram_gb: Some(full_ram),
+                    ..Default::default()
                 }),
                 steps: {
                     let mut steps = BTreeMap::new();
@@ -245,6 +1112,7 @@ mod tests {
                     );
                     steps
                 },
+                ..Default::default()
             },
         );
         modes.insert(
@@ -254,6 +1122,7 @@ mod tests {
                     os: vec![parse_os_constraint("macos>=12").unwrap()],
                     cpu_arch: vec![],
                     ram_gb: Some(light_ram),
+                    ..Default::default()
                 }),
                 steps: {
                     let mut steps = BTreeMap::new();
@@ -265,6 +1134,7 @@ mod tests {
                     );
                     steps
                 },
+                ..Default::default()
             },
         );
 
@@ -272,6 +1142,7 @@ mod tests {
             name: "demo".into(),
             version: "1.0.0".into(),
             modes,
+            ..Default::default()
         }
     }
 
@@ -294,6 +1165,115 @@ mod tests {
         assert_eq!(plan.chosen_mode, "light");
     }
 
+    #[test]
+    fn priority_outranks_ram_heuristic() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("light").unwrap().priority = Some(10);
+
+        let plan = plan_install(&manifest, &base_env()).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "light");
+        assert_eq!(plan.compatible_modes, ["light", "full"]);
+        assert!(plan.selection_reason.contains("highest priority (10"));
+    }
+
+    #[test]
+    fn mode_order_outranks_priority() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("full").unwrap().priority = Some(10);
+        manifest.mode_order = vec!["light".into()];
+
+        let plan = plan_install(&manifest, &base_env()).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "light");
+        assert!(plan.selection_reason.contains("mode_order"));
+    }
+
+    #[test]
+    fn ties_break_by_ram_then_name() {
+        let manifest = manifest_with_modes(8, 8);
+
+        let plan = plan_install(&manifest, &base_env()).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "full");
+        assert!(plan.selection_reason.contains("sorts first by name"));
+
+        let manifest = manifest_with_modes(4, 8);
+        let plan = plan_install(&manifest, &base_env()).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "light");
+        assert!(plan.selection_reason.contains("requires more RAM"));
+    }
+
+    #[test]
+    fn forced_mode_overrides_ranking() {
+        let manifest = manifest_with_modes(8, 4);
+        let options = PlanOptions {
+            mode: Some("light".into()),
+            ..Default::default()
+        };
+
+        let plan = plan_install_with_options(&manifest, &base_env(), &options)
+            .expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "light");
+        assert!(plan.warnings.is_empty());
+    }
+
+    #[test]
+    fn forced_incompatible_mode_requires_force() {
+        let manifest = manifest_with_modes(32, 4);
+        let mut options = PlanOptions {
+            mode: Some("full".into()),
+            ..Default::default()
+        };
+
+        let err = plan_install_with_options(&manifest, &base_env(), &options)
+            .expect_err("incompatible forced mode should fail");
+        match err {
+            super::PlannerError::IncompatibleMode {
+                reasons, forceable, ..
+            } => {
+                assert!(forceable);
+                assert!(reasons[0].contains("32 GiB"));
+            }
+            other => panic!("unexpected error {other}"),
+        }
+
+        options.force = true;
+        let plan = plan_install_with_options(&manifest, &base_env(), &options)
+            .expect("forced plan should succeed");
+        assert_eq!(plan.chosen_mode, "full");
+        assert!(plan.warnings[0].contains("32 GiB"));
+    }
+
+    #[test]
+    fn forcing_cannot_supply_missing_platform_steps() {
+        let manifest = manifest_with_modes(8, 4);
+        let mut env = base_env();
+        env.os = "windows".into();
+        let options = PlanOptions {
+            mode: Some("full".into()),
+            force: true,
+            ..Default::default()
+        };
+
+        let err = plan_install_with_options(&manifest, &env, &options)
+            .expect_err("no windows steps to force");
+        assert!(matches!(
+            err,
+            super::PlannerError::IncompatibleMode {
+                forceable: false,
+                ..
+            }
+        ));
+        let err = plan_install_with_options(
+            &manifest,
+            &base_env(),
+            &PlanOptions {
+                mode: Some("gpu".into()),
+                ..Default::default()
+            },
+        )
+        .expect_err("unknown mode");
+        assert!(matches!(err, super::PlannerError::UnknownMode(_)));
+    }
+
     #[test]
     fn surfaces_reasons_when_no_modes_match() {
         let manifest = manifest_with_modes(32, 16);
@@ -306,7 +1286,734 @@ mod tests {
             super::PlannerError::NoCompatibleMode { reasons, .. } => {
                 assert!(reasons.iter().any(|r| r.contains("requires")));
             }
+            other => panic!("unexpected error {other}"),
+        }
+    }
+
+    #[test]
+    fn explain_reports_every_failed_requirement() {
+        let manifest = manifest_with_modes(32, 4);
+        let mut env = base_env();
+        env.cpu_arch = "x64".into();
+        env.os_version = "12.6".into();
+
+        let explanation = super::explain_modes(&manifest, &env);
+        let full = &explanation.modes[0];
+        assert_eq!(full.name, "full");
+        assert!(!full.compatible);
+        let failed: Vec<&str> = full
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.requirement.as_str())
+            .collect();
+        assert_eq!(failed, vec!["os", "cpu_arch", "ram_gb"]);
+        assert_eq!(full.checks[1].expected, "macos>=13");
+        assert_eq!(full.checks[3].actual, "16 GiB");
+
+        let light = &explanation.modes[1];
+        assert!(light.compatible && light.chosen);
+        assert_eq!(explanation.chosen_mode.as_deref(), Some("light"));
+        assert!(
+            explanation
+                .selection_reason
+                .unwrap()
+                .contains("only compatible")
+        );
+    }
+
+    #[test]
+    fn any_matching_os_constraint_satisfies_the_requirement() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest
+            .modes
+            .get_mut("full")
+            .unwrap()
+            .requirements
+            .as_mut()
+            .unwrap()
+            .os
+            .insert(0, parse_os_constraint("windows>=10").unwrap());
+
+        let explanation = super::explain_modes(&manifest, &base_env());
+        let full = &explanation.modes[0];
+        assert!(!full.checks[1].passed);
+        assert!(full.checks[2].passed);
+        assert!(full.compatible && full.chosen);
+    }
+
+    #[test]
+    fn enforces_disk_cores_and_cpu_features() {
+        let mut manifest = manifest_with_modes(8, 4);
+        let requirements = manifest
+            .modes
+            .get_mut("full")
+            .unwrap()
+            .requirements
+            .as_mut()
+            .unwrap();
+        requirements.disk_free_gb = Some(20);
+        requirements.cpu_cores = Some(4);
+        requirements.cpu_features = vec!["NEON".into(), "sve".into()];
+
+        let mut env = base_env();
+        env.disk_free_gb = Some(12);
+        env.cpu_cores = None;
+
+        let explanation = super::explain_modes(&manifest, &env);
+        let failed: Vec<(&str, &str)> = explanation.modes[0]
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| (check.requirement.as_str(), check.actual.as_str()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                ("disk_free_gb", "12 GiB free"),
+                ("cpu_cores", "unknown"),
+                ("cpu_features", "neon (missing sve)"),
+            ]
+        );
+        assert_eq!(explanation.chosen_mode.as_deref(), Some("light"));
+
+        env.disk_free_gb = Some(50);
+        env.cpu_cores = Some(8);
+        env.cpu_features.push("sve".into());
+        let plan = plan_install(&manifest, &env).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "full");
+    }
+
+    #[test]
+    fn install_target_holds_each_modes_destinations() {
+        let json = r#"{
+            "name": "keanu",
+            "version": "1.0.0",
+            "modes": {
+                "full": {
+                    "steps": { "macos": [
+                        {"download": {"url": "https://cdn.example.com/a.zip", "dest": "{{root}}/cache/a.zip"}},
+                        {"extract": {"archive": "{{root}}/cache/a.zip", "dest": "{{root}}/app"}}
+                    ] }
+                },
+                "light": {
+                    "steps": { "macos": [
+                        {"download": {"url": "https://cdn.example.com/b.zip", "dest": "{{home}}/b.zip"}}
+                    ] }
+                },
+                "server": {
+                    "steps": { "linux": [
+                        {"download": {"url": "https://cdn.example.com/c.zip", "dest": "{{root}}/c.zip"}}
+                    ] }
+                }
+            }
+        }"#;
+        let manifest: Manifest = serde_json::from_str(json).expect("manifest should parse");
+        let dir = tempfile::tempdir().unwrap();
+        let (root, home) = (dir.path().join("opt/keanu"), dir.path().join("home/.keanu"));
+        let mut options = PlanOptions::default();
+        options
+            .vars
+            .insert("root".into(), root.to_string_lossy().into_owned());
+        options
+            .vars
+            .insert("home".into(), home.to_string_lossy().into_owned());
+
+        let target = |name| super::install_target(&manifest, name, &base_env(), &options);
+        assert_eq!(target("full"), root);
+        assert_eq!(target("light"), home);
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(target("server"), cwd);
+    }
+
+    #[test]
+    fn checks_disk_space_at_each_modes_target() {
+        let mut manifest = manifest_with_modes(8, 4);
+        let requirements = manifest
+            .modes
+            .get_mut("full")
+            .unwrap()
+            .requirements
+            .as_mut()
+            .unwrap();
+        requirements.disk_free_gb = Some(20);
+
+        let mut env = base_env();
+        env.disk_free_gb = Some(50);
+        env.disk_free_gb_by_mode.insert("full".into(), 12);
+        let plan = plan_install(&manifest, &env).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "light");
+
+        env.disk_free_gb = Some(5);
+        env.disk_free_gb_by_mode.insert("full".into(), 80);
+        let plan = plan_install(&manifest, &env).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "full");
+    }
+
+    #[test]
+    fn reports_missing_cpu_features() {
+        let mut manifest = manifest_with_modes(8, 4);
+        for mode in manifest.modes.values_mut() {
+            mode.requirements.as_mut().unwrap().cpu_features = vec!["avx2".into()];
         }
+
+        let err = plan_install(&manifest, &base_env()).expect_err("no avx2 on arm");
+        match err {
+            super::PlannerError::NoCompatibleMode { reasons, .. } => {
+                assert_eq!(
+                    reasons[0],
+                    "full: requires cpu_features avx2, found none (missing avx2)"
+                );
+            }
+            other => panic!("unexpected error {other}"),
+        }
+    }
+
+    #[test]
+    fn checks_commands_and_package_managers() {
+        let mut manifest = manifest_with_modes(8, 4);
+        let full = manifest.modes.get_mut("full").unwrap();
+        let requirements = full.requirements.as_mut().unwrap();
+        requirements.commands = vec![
+            parse_command_requirement("git>=2.30").unwrap(),
+            parse_command_requirement("psql").unwrap(),
+        ];
+        requirements.pkg_managers = vec!["brew".into(), "port".into()];
+
+        let mut env = base_env();
+        env.commands.insert("git".into(), "2.25.1".into());
+
+        let explanation = super::explain_modes(&manifest, &env);
+        let failed: Vec<(&str, &str, &str)> = explanation.modes[0]
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| {
+                (
+                    check.requirement.as_str(),
+                    check.expected.as_str(),
+                    check.actual.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                ("command:git", "git>=2.30", "git 2.25.1"),
+                ("command:psql", "on PATH", "none"),
+                ("pkg_managers", "brew or port", "none"),
+            ]
+        );
+
+        env.commands.insert("git".into(), "2.43.0".into());
+        env.commands.insert("psql".into(), "unknown".into());
+        env.pkg_managers.push("brew".into());
+        let plan = plan_install(&manifest, &env).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "full");
+    }
+
+    #[test]
+    fn unmet_recommendations_lower_ranking_and_warn() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("full").unwrap().recommended = Some(requirements_json(
+            r#"{ "ram_gb": 32, "commands": ["docker"] }"#,
+        ));
+
+        let plan = plan_install(&manifest, &base_env()).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "light");
+        assert_eq!(plan.compatible_modes, vec!["light", "full"]);
+        assert_eq!(
+            plan.selection_reason,
+            "tied with 'full' on priority 0; 'light' misses fewer recommendations (0 vs 2)"
+        );
+        assert!(plan.warnings.is_empty());
+
+        let options = PlanOptions {
+            mode: Some("full".into()),
+            ..Default::default()
+        };
+        let plan = plan_install_with_options(&manifest, &base_env(), &options)
+            .expect("unmet recommendations do not need --force");
+        assert_eq!(
+            plan.warnings,
+            vec![
+                "mode 'full' recommendation not met: requires ram_gb >= 32 GiB, found 16 GiB",
+                "mode 'full' recommendation not met: requires command:docker on PATH, found none",
+            ]
+        );
+    }
+
+    #[test]
+    fn installed_recommended_commands_do_not_penalise_the_mode() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("full").unwrap().recommended =
+            Some(requirements_json(r#"{ "commands": ["docker>=24"] }"#));
+
+        let mut env = base_env();
+        env.commands.insert("docker".into(), "27.1.1".into());
+        let plan = plan_install(&manifest, &env).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "full");
+        assert!(plan.warnings.is_empty());
+        let explanation = super::explain_modes(&manifest, &env);
+        assert!(explanation.modes[0].warnings.is_empty());
+    }
+
+    #[test]
+    fn explain_labels_recommended_checks() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("light").unwrap().recommended = Some(requirements_json(
+            r#"{ "any": [{ "cpu_cores": 16 }, { "disk_free_gb": 500 }] }"#,
+        ));
+
+        let explanation = super::explain_modes(&manifest, &base_env());
+        let light = &explanation.modes[1];
+        assert!(light.compatible);
+        let labels: Vec<&str> = light
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.requirement.as_str())
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                "recommended.any[0].cpu_cores",
+                "recommended.any[1].disk_free_gb"
+            ]
+        );
+        assert_eq!(light.warnings.len(), 1);
+        assert!(light.warnings[0].starts_with("no alternative in any is met"));
+    }
+
+    fn explain_outcomes(
+        requirements: &str,
+        recommended: Option<&str>,
+    ) -> (bool, Vec<(String, CheckOutcome)>) {
+        let mut manifest = manifest_with_modes(8, 4);
+        let full = manifest.modes.get_mut("full").unwrap();
+        full.requirements = Some(requirements_json(requirements));
+        full.recommended = recommended.map(requirements_json);
+        let explanation = super::explain_modes(&manifest, &base_env());
+        let full = &explanation.modes[0];
+        let outcomes = full
+            .checks
+            .iter()
+            .skip(1)
+            .map(|check| (check.requirement.clone(), check.outcome.unwrap()))
+            .collect();
+        (full.compatible, outcomes)
+    }
+
+    fn labelled(outcomes: &[(&str, CheckOutcome)]) -> Vec<(String, CheckOutcome)> {
+        outcomes
+            .iter()
+            .map(|(label, outcome)| (label.to_string(), *outcome))
+            .collect()
+    }
+
+    #[test]
+    fn outcome_of_other_os_entries_is_not_needed() {
+        let (compatible, outcomes) =
+            explain_outcomes(r#"{ "os": ["windows", "macos>=13"] }"#, None);
+        assert!(compatible);
+        assert_eq!(
+            outcomes,
+            labelled(&[("os", CheckOutcome::NotNeeded), ("os", CheckOutcome::Pass)])
+        );
+    }
+
+    #[test]
+    fn outcome_of_a_matched_not_is_excluded() {
+        let (compatible, outcomes) =
+            explain_outcomes(r#"{ "not": { "os": ["macos==14.*"], "ram_gb": 8 } }"#, None);
+        assert!(!compatible);
+        assert_eq!(
+            outcomes,
+            labelled(&[
+                ("not.os", CheckOutcome::Excluded),
+                ("not.ram_gb", CheckOutcome::Excluded),
+            ])
+        );
+    }
+
+    #[test]
+    fn outcome_inside_an_unmet_not_credits_the_failing_check() {
+        let (compatible, outcomes) =
+            explain_outcomes(r#"{ "not": { "os": ["macos"], "ram_gb": 64 } }"#, None);
+        assert!(compatible);
+        assert_eq!(
+            outcomes,
+            labelled(&[
+                ("not.os", CheckOutcome::NotNeeded),
+                ("not.ram_gb", CheckOutcome::Pass),
+            ])
+        );
+    }
+
+    #[test]
+    fn outcome_of_unused_any_branches_is_not_needed() {
+        let (compatible, outcomes) = explain_outcomes(
+            r#"{ "any": [ { "cpu_arch": ["x64"] }, { "ram_gb": 8 } ] }"#,
+            None,
+        );
+        assert!(compatible);
+        assert_eq!(
+            outcomes,
+            labelled(&[
+                ("any[0].cpu_arch", CheckOutcome::NotNeeded),
+                ("any[1].ram_gb", CheckOutcome::Pass),
+            ])
+        );
+
+        let (compatible, outcomes) = explain_outcomes(
+            r#"{ "any": [ { "cpu_arch": ["x64"] }, { "ram_gb": 64 } ] }"#,
+            None,
+        );
+        assert!(!compatible);
+        assert_eq!(
+            outcomes,
+            labelled(&[
+                ("any[0].cpu_arch", CheckOutcome::Fail),
+                ("any[1].ram_gb", CheckOutcome::Fail),
+            ])
+        );
+    }
+
+    #[test]
+    fn outcome_of_recommended_failures_is_warn() {
+        let (compatible, outcomes) = explain_outcomes(
+            r#"{ "ram_gb": 8 }"#,
+            Some(r#"{ "ram_gb": 32, "not": { "cpu_arch": ["arm64"] } }"#),
+        );
+        assert!(compatible);
+        assert_eq!(
+            outcomes,
+            labelled(&[
+                ("ram_gb", CheckOutcome::Pass),
+                ("recommended.ram_gb", CheckOutcome::Warn),
+                ("recommended.not.cpu_arch", CheckOutcome::Warn),
+            ])
+        );
+    }
+
+    fn requirements_json(json: &str) -> Requirements {
+        serde_json::from_str(json).expect("requirements parse")
+    }
+
+    #[test]
+    fn any_requires_one_matching_alternative() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("full").unwrap().requirements = Some(requirements_json(
+            r#"{ "any": [ { "cpu_arch": ["x64"], "ram_gb": 8 }, { "cpu_arch": ["arm64"], "ram_gb": 32 } ] }"#,
+        ));
+
+        let mut env = base_env();
+        let explanation = super::explain_modes(&manifest, &env);
+        assert_eq!(
+            explanation.modes[0].reason.as_deref(),
+            Some(
+                "no alternative in any is met: requires any[0].cpu_arch x64, found arm64; \
+                 requires any[1].ram_gb >= 32 GiB, found 16 GiB"
+            )
+        );
+        let labels: Vec<&str> = explanation.modes[0]
+            .checks
+            .iter()
+            .map(|check| check.requirement.as_str())
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                "platform_steps",
+                "any[0].cpu_arch",
+                "any[0].ram_gb",
+                "any[1].cpu_arch",
+                "any[1].ram_gb"
+            ]
+        );
+
+        env.ram_gb = 32;
+        let plan = plan_install(&manifest, &env).expect("arm64 branch matches");
+        assert!(plan.compatible_modes.contains(&"full".to_string()));
+    }
+
+    #[test]
+    fn not_excludes_matching_environments() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("full").unwrap().requirements = Some(requirements_json(
+            r#"{ "os": ["macos>=13"], "not": { "os": ["macos==14.*"] } }"#,
+        ));
+
+        let mut env = base_env();
+        let explanation = super::explain_modes(&manifest, &env);
+        assert_eq!(
+            explanation.modes[0].reason.as_deref(),
+            Some("not is met: not.os macos==14.*, found macos 14.0")
+        );
+        assert_eq!(explanation.chosen_mode.as_deref(), Some("light"));
+
+        env.os_version = "15.1".into();
+        let plan = plan_install(&manifest, &env).expect("macOS 15 is allowed");
+        assert!(plan.compatible_modes.contains(&"full".to_string()));
+    }
+
+    #[test]
+    fn all_reports_the_failing_nested_path() {
+        let mut manifest = manifest_with_modes(8, 4);
+        manifest.modes.get_mut("full").unwrap().requirements = Some(requirements_json(
+            r#"{ "all": [ { "os": ["macos"] }, { "any": [ { "cpu_cores": 16 }, { "ram_gb": 64 } ] } ] }"#,
+        ));
+
+        let err = plan_install_with_options(
+            &manifest,
+            &base_env(),
+            &PlanOptions {
+                mode: Some("full".into()),
+                ..Default::default()
+            },
+        )
+        .expect_err("neither alternative holds");
+        match err {
+            super::PlannerError::IncompatibleMode { reasons, .. } => {
+                assert_eq!(
+                    reasons[0],
+                    "no alternative in all[1].any is met: \
+                     requires all[1].any[0].cpu_cores >= 16 cores, found 8 cores; \
+                     requires all[1].any[1].ram_gb >= 64 GiB, found 16 GiB"
+                );
+            }
+            other => panic!("unexpected error {other}"),
+        }
+    }
+
+    #[test]
+    fn plans_linux_steps_on_linux() {
+        let json = r#"{
+            "name": "fleet",
+            "version": "1.0.0",
+            "modes": {
+                "full": {
+                    "requirements": { "os": ["linux"], "ram_gb": 2 },
+                    "steps": {
+                        "macos": [{"run": "brew install node"}],
+                        "linux": [{"run": "apt-get install -y nodejs"}]
+                    }
+                }
+            }
+        }"#;
+        let manifest: Manifest = serde_json::from_str(json).expect("manifest should parse");
+        let mut env = base_env();
+        env.os = "linux".into();
+        env.os_version = "6.5".into();
+        env.pkg_managers = vec!["apt".into()];
+
+        let plan = plan_install(&manifest, &env).expect("plan should succeed");
+        assert_eq!(plan.os, "linux");
+        assert_eq!(
+            plan.steps[0].command.as_deref(),
+            Some("apt-get install -y nodejs")
+        );
+    }
+
+    fn linux_env(distro_id: &str, version: &str, id_like: &[&str]) -> Environment {
+        let mut env = base_env();
+        env.os = "linux".into();
+        env.os_version = "6.5".into();
+        env.distro_id = Some(distro_id.into());
+        env.distro_version = Some(version.into());
+        env.id_like = id_like.iter().map(|id| id.to_string()).collect();
+        env
+    }
+
+    #[test]
+    fn distro_constraints_match_id_and_id_like() {
+        let ubuntu = parse_os_constraint("ubuntu>=22.04").unwrap();
+        let debian = parse_os_constraint("debian>=12").unwrap();
+        let rhel = parse_os_constraint("rhel>=9").unwrap();
+
+        assert!(os_matches(
+            &linux_env("ubuntu", "22.04", &["debian"]),
+            &ubuntu
+        ));
+        assert!(!os_matches(
+            &linux_env("ubuntu", "20.04", &["debian"]),
+            &ubuntu
+        ));
+        assert!(os_matches(&linux_env("debian", "12", &[]), &debian));
+        assert!(!os_matches(&linux_env("debian", "11", &[]), &debian));
+        assert!(os_matches(&linux_env("rhel", "9.3", &[]), &rhel));
+        assert!(!os_matches(&linux_env("fedora", "39", &[]), &debian));
+    }
+
+    #[test]
+    fn id_like_lets_versioned_constraints_match_derivatives() {
+        let debian = parse_os_constraint("debian").unwrap();
+        let debian_12 = parse_os_constraint("debian>=12").unwrap();
+        let rhel_9 = parse_os_constraint("rhel>=9").unwrap();
+
+        let jammy = linux_env("ubuntu", "22.04", &["debian"]);
+        assert!(os_matches(&jammy, &debian));
+        assert!(os_matches(&jammy, &debian_12));
+        assert!(os_matches(
+            &linux_env("rocky", "9.3", &["rhel", "centos", "fedora"]),
+            &rhel_9
+        ));
+        assert!(os_matches(
+            &linux_env("almalinux", "9.4", &["rhel", "centos", "fedora"]),
+            &rhel_9
+        ));
+        assert!(!os_matches(
+            &linux_env("rocky", "8.9", &["rhel", "centos", "fedora"]),
+            &rhel_9
+        ));
+    }
+
+    #[test]
+    fn distro_constraint_does_not_match_other_platforms() {
+        let ubuntu = parse_os_constraint("ubuntu").unwrap();
+        assert!(!os_matches(&base_env(), &ubuntu));
+    }
+
+    #[test]
+    fn libc_requirement_selects_the_matching_build() {
+        let json = r#"{
+            "name": "fleet",
+            "version": "1.0.0",
+            "modes": {
+                "glibc": {
+                    "requirements": { "libc": ["glibc>=2.31"] },
+                    "steps": { "linux": [
+                        {"download": {"url": "https://cdn.example.com/app-{{libc}}.tar.gz", "dest": "app.tar.gz"}}
+                    ] }
+                },
+                "musl": {
+                    "requirements": { "libc": ["musl"] },
+                    "steps": { "linux": [{"run": "echo {{libc}} {{libc_version}}"}] }
+                }
+            }
+        }"#;
+        let manifest: Manifest = serde_json::from_str(json).expect("manifest should parse");
+
+        let mut alpine = linux_env("alpine", "3.19.1", &[]);
+        alpine.libc = Some("musl".into());
+        alpine.libc_version = Some("1.2.4".into());
+        let plan = plan_install(&manifest, &alpine).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "musl");
+        assert_eq!(plan.steps[0].command.as_deref(), Some("echo musl 1.2.4"));
+
+        let mut focal = linux_env("ubuntu", "20.04", &["debian"]);
+        focal.libc = Some("glibc".into());
+        focal.libc_version = Some("2.31".into());
+        let plan = plan_install(&manifest, &focal).expect("plan should succeed");
+        assert_eq!(plan.chosen_mode, "glibc");
+        match &plan.steps[0].step {
+            Step::Download { download } => {
+                assert_eq!(download.url, "https://cdn.example.com/app-glibc.tar.gz");
+            }
+            other => panic!("unexpected step {other:?}"),
+        }
+
+        let mut bionic = linux_env("ubuntu", "18.04", &["debian"]);
+        bionic.libc = Some("glibc".into());
+        bionic.libc_version = Some("2.27".into());
+        match plan_install(&manifest, &bionic) {
+            Err(super::PlannerError::NoCompatibleMode { reasons, .. }) => {
+                assert_eq!(
+                    reasons,
+                    vec![
+                        "glibc: requires libc glibc>=2.31, found glibc 2.27",
+                        "musl: requires libc musl, found glibc 2.27",
+                    ]
+                );
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn interpolates_vars_into_step_fields() {
+        let json = r#"{
+            "name": "templated",
+            "version": "2.0.0",
+            "vars": { "cdn": "https://cdn.example.com/{{app_version}}", "channel": "stable" },
+            "modes": {
+                "full": {
+                    "vars": { "channel": "beta" },
+                    "steps": {
+                        "macos": [
+                            {"run": "echo {{channel}} on {{os}}-{{cpu_arch}}"},
+                            {"download": {"url": "{{cdn}}/app-{{os}}.zip", "dest": "artifacts/{{app_version}}/app.zip"}},
+                            {"extract": {"archive": "artifacts/{{app_version}}/app.zip", "dest": "{{install_root}}"}}
+                        ]
+                    }
+                }
+            }
+        }"#;
+        let manifest: Manifest = serde_json::from_str(json).expect("manifest should parse");
+        let mut options = PlanOptions::default();
+        options
+            .vars
+            .insert("install_root".into(), "/opt/{{app_name}}".into());
+
+        let plan = plan_install_with_options(&manifest, &base_env(), &options)
+            .expect("plan should succeed");
+
+        assert_eq!(
+            plan.steps[0].command.as_deref(),
+            Some("echo beta on macos-arm64")
+        );
+        match &plan.steps[1].step {
+            Step::Download { download } => {
+                assert_eq!(download.url, "https://cdn.example.com/2.0.0/app-macos.zip");
+                assert_eq!(
+                    download.dest,
+                    std::path::PathBuf::from("artifacts/2.0.0/app.zip")
+                );
+            }
+            other => panic!("unexpected step {other:?}"),
+        }
+        match &plan.steps[2].step {
+            Step::Extract { extract } => {
+                assert_eq!(extract.dest, std::path::PathBuf::from("/opt/templated"));
+            }
+            other => panic!("unexpected step {other:?}"),
+        }
+        assert_eq!(plan.vars["channel"], "beta");
+    }
+
+    #[test]
+    fn overrides_reach_derived_vars_and_cycles_fail() {
+        let json = r#"{
+            "name": "templated",
+            "version": "2.0.0",
+            "vars": { "base": "https://cdn", "url": "{{base}}/{{app_version}}/x" },
+            "modes": {
+                "full": {
+                    "steps": { "macos": [ {"download": {"url": "{{url}}", "dest": "x"}} ] }
+                }
+            }
+        }"#;
+        let manifest: Manifest = serde_json::from_str(json).expect("manifest should parse");
+        let plan = plan_install(&manifest, &base_env()).expect("plan should succeed");
+        assert_eq!(plan.vars["url"], "https://cdn/2.0.0/x");
+
+        let mut options = PlanOptions::default();
+        options.vars.insert("app_version".into(), "2.1.0".into());
+        options.vars.insert("base".into(), "https://mirror".into());
+        let plan = plan_install_with_options(&manifest, &base_env(), &options)
+            .expect("plan should succeed");
+        match &plan.steps[0].step {
+            Step::Download { download } => assert_eq!(download.url, "https://mirror/2.1.0/x"),
+            other => panic!("unexpected step {other:?}"),
+        }
+
+        options.vars.insert("base".into(), "{{url}}".into());
+        let err = plan_install_with_options(&manifest, &base_env(), &options)
+            .expect_err("base and url reference each other");
+        assert_eq!(
+            err.to_string(),
+            "variable reference cycle: base -> url -> base"
+        );
     }
 
     #[test]
@@ -0,0 +1,198 @@
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+use crate::download::{DownloadProgress, ProgressCallback};
+
+/// Interval between plain progress lines when output is not a terminal.
+const LINE_INTERVAL: Duration = Duration::from_secs(5);
+/// Interval between `--json` progress events.
+const JSON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Progress reporting for the CLI: JSON lines on stderr with `--json`, a
+/// progress bar when stderr is a terminal, and periodic plain lines otherwise.
+pub fn cli_reporter(json: bool) -> ProgressCallback {
+    if json {
+        json_reporter()
+    } else if std::io::stderr().is_terminal() {
+        bar_reporter()
+    } else {
+        line_reporter()
+    }
+}
+
+fn bar_reporter() -> ProgressCallback {
+    let bar: Mutex<Option<ProgressBar>> = Mutex::new(None);
+    ProgressCallback::new(move |progress| {
+        let mut slot = bar.lock().unwrap_or_else(|err| err.into_inner());
+        let bar = slot.get_or_insert_with(|| new_bar(progress));
+        if let Some(total) = progress.total {
+            bar.set_length(total);
+        }
+        bar.set_position(progress.downloaded);
+        if progress.failed {
+            bar.abandon();
+            *slot = None;
+        } else if progress.finished {
+            bar.finish();
+            *slot = None;
+        }
+    })
+}
+
+fn new_bar(progress: &DownloadProgress<'_>) -> ProgressBar {
+    let (bar, template) = match progress.total {
+        Some(total) => (
+            ProgressBar::new(total),
+            "    [{bar:30}] {bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta}",
+        ),
+        None => (
+            ProgressBar::new_spinner(),
+            "    {spinner} {bytes} {binary_bytes_per_sec}",
+        ),
+    };
+    bar.set_style(
+        ProgressStyle::with_template(template)
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> "),
+    );
+    // Start the rate and ETA from any resumed bytes rather than from zero.
+    bar.set_position(progress.downloaded);
+    bar.reset_eta();
+    bar
+}
+
+fn line_reporter() -> ProgressCallback {
+    let last: Mutex<Option<Instant>> = Mutex::new(None);
+    ProgressCallback::new(move |progress| {
+        if due(&last, progress, LINE_INTERVAL) {
+            eprintln!("    {}", describe_progress(progress));
+        }
+    })
+}
+
+#[derive(Serialize)]
+struct ProgressEvent<'a> {
+    event: &'static str,
+    #[serde(flatten)]
+    progress: &'a DownloadProgress<'a>,
+}
+
+fn json_reporter() -> ProgressCallback {
+    let last: Mutex<Option<Instant>> = Mutex::new(None);
+    ProgressCallback::new(move |progress| {
+        if due(&last, progress, JSON_INTERVAL)
+            && let Ok(line) = serde_json::to_string(&ProgressEvent {
+                event: "download_progress",
+                progress,
+            })
+        {
+            eprintln!("{line}");
+        }
+    })
+}
+
+/// Whether to report this event: the first and last of a download, and
+/// otherwise at most once per `interval`. The last event, finished or
+/// failed, resets the throttle for the next download.
+fn due(last: &Mutex<Option<Instant>>, progress: &DownloadProgress<'_>, interval: Duration) -> bool {
+    let mut last = last.lock().unwrap_or_else(|err| err.into_inner());
+    let now = Instant::now();
+    let ended = progress.finished || progress.failed;
+    let due = ended || last.is_none_or(|at| now.duration_since(at) >= interval);
+    if due {
+        *last = (!ended).then_some(now);
+    }
+    due
+}
+
+/// One-line summary such as `12.00 MiB of 100.00 MiB (12%)`.
+pub fn describe_progress(progress: &DownloadProgress<'_>) -> String {
+    let downloaded = HumanBytes(progress.downloaded);
+    match progress.total {
+        _ if progress.failed => format!("download failed after {downloaded}"),
+        _ if progress.finished => format!("downloaded {downloaded}"),
+        Some(total) if total > 0 => format!(
+            "{downloaded} of {} ({}%)",
+            HumanBytes(total),
+            progress.downloaded * 100 / total
+        ),
+        _ => format!("{downloaded} so far"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::{ProgressEvent, describe_progress, due};
+    use crate::download::DownloadProgress;
+
+    fn progress(downloaded: u64, total: Option<u64>, finished: bool) -> DownloadProgress<'static> {
+        DownloadProgress {
+            url: "https://cdn.example.com/model.bin",
+            dest: Path::new("artifacts/model.bin"),
+            downloaded,
+            total,
+            finished,
+            failed: false,
+        }
+    }
+
+    fn failed(downloaded: u64) -> DownloadProgress<'static> {
+        DownloadProgress {
+            failed: true,
+            ..progress(downloaded, None, false)
+        }
+    }
+
+    #[test]
+    fn describes_progress_with_and_without_a_total() {
+        assert_eq!(
+            describe_progress(&progress(12 << 20, Some(100 << 20), false)),
+            "12.00 MiB of 100.00 MiB (12%)"
+        );
+        assert_eq!(
+            describe_progress(&progress(3 << 10, None, false)),
+            "3.00 KiB so far"
+        );
+        assert_eq!(
+            describe_progress(&progress(100 << 20, Some(100 << 20), true)),
+            "downloaded 100.00 MiB"
+        );
+        assert_eq!(
+            describe_progress(&failed(5 << 20)),
+            "download failed after 5.00 MiB"
+        );
+    }
+
+    #[test]
+    fn throttles_all_but_the_first_and_last_event() {
+        let last = Mutex::new(None);
+        let interval = Duration::from_secs(60);
+        assert!(due(&last, &progress(0, Some(10), false), interval));
+        assert!(!due(&last, &progress(5, Some(10), false), interval));
+        assert!(due(&last, &progress(10, Some(10), true), interval));
+        assert!(due(&last, &progress(0, Some(20), false), interval));
+        assert!(due(&last, &failed(5), interval));
+        assert!(due(&last, &progress(0, Some(20), false), interval));
+    }
+
+    #[test]
+    fn serializes_json_events() {
+        let progress = progress(5, Some(10), false);
+        let event = ProgressEvent {
+            event: "download_progress",
+            progress: &progress,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"download_progress","url":"https://cdn.example.com/model.bin","dest":"artifacts/model.bin","downloaded":5,"total":10,"finished":false,"failed":false}"#
+        );
+    }
+}
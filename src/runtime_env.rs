@@ -8,7 +8,7 @@ use which::which;
 use crate::manifest::{RuntimeEnv, RuntimeEnvType};
 use crate::planner::InstallPlan;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ExecutionContext {
     pub env: HashMap<String, String>,
     pub path_prefixes: Vec<PathBuf>,
@@ -16,10 +16,7 @@ pub struct ExecutionContext {
 
 impl ExecutionContext {
     pub fn new() -> Self {
-        Self {
-            env: HashMap::new(),
-            path_prefixes: Vec::new(),
-        }
+        Self::default()
     }
 
     pub fn merged_path(&self) -> Option<String> {
@@ -72,11 +69,7 @@ fn prepare_node_env(
     std::fs::create_dir_all(&node_root)
         .with_context(|| format!("creating node runtime at {}", node_root.display()))?;
 
-    let bin_dir = if os == "windows" {
-        node_root.join("bin")
-    } else {
-        node_root.join("bin")
-    };
+    let bin_dir = node_root.join("bin");
 
     let local_node = bin_dir.join(if os == "windows" { "node.exe" } else { "node" });
     if local_node.exists() {
@@ -90,10 +83,8 @@ fn prepare_node_env(
         .as_ref()
         .and_then(|n| n.install_strategy.clone())
         .unwrap_or_else(|| "local_bundle_or_global".to_string());
-    if strategy.contains("global") {
-        if which("node").is_ok() {
-            return Ok(());
-        }
+    if strategy.contains("global") && which("node").is_ok() {
+        return Ok(());
     }
 
     Err(anyhow!(
@@ -5,6 +5,8 @@ use anyhow::{Context, anyhow};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::download::DownloadRecord;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum InstallStatus {
@@ -21,6 +23,9 @@ pub struct InstallRecord {
     pub cpu_arch: String,
     pub timestamp: DateTime<Utc>,
     pub status: InstallStatus,
+    /// The URL each download step fetched its file from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub downloads: Vec<DownloadRecord>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::env_detect::Environment;
+use crate::manifest::{Manifest, Step};
+
+/// Variables resolved for a plan, keyed by name.
+pub type Vars = BTreeMap<String, String>;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VarsError {
+    #[error("variable reference cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// Facts about the target machine and manifest available to every step.
+pub fn builtin_vars(manifest: &Manifest, mode_name: &str, env: &Environment) -> Vars {
+    let mut vars = Vars::new();
+    vars.insert("app_name".to_string(), manifest.name.clone());
+    vars.insert("app_version".to_string(), manifest.version.clone());
+    vars.insert("mode".to_string(), mode_name.to_string());
+    vars.insert("os".to_string(), env.os.clone());
+    vars.insert("os_version".to_string(), env.os_version.clone());
+    vars.insert("cpu_arch".to_string(), env.cpu_arch.clone());
+    vars.insert("ram_gb".to_string(), env.ram_gb.to_string());
+    if let Some(distro_id) = &env.distro_id {
+        vars.insert("distro_id".to_string(), distro_id.clone());
+    }
+    if let Some(distro_version) = &env.distro_version {
+        vars.insert("distro_version".to_string(), distro_version.clone());
+    }
+    if let Some(libc) = &env.libc {
+        vars.insert("libc".to_string(), libc.clone());
+    }
+    if let Some(libc_version) = &env.libc_version {
+        vars.insert("libc_version".to_string(), libc_version.clone());
+    }
+    vars
+}
+
+/// Add a layer of variables on top of `vars`; later layers win on name
+/// clashes. References are expanded afterwards by [`resolve_references`], so
+/// they see the final value of every variable whichever layer set it.
+pub fn apply_layer(vars: &mut Vars, layer: &BTreeMap<String, String>) {
+    vars.extend(
+        layer
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+}
+
+/// Expand `{{name}}` references between variables, recursively and in any
+/// order, so a value may use variables from its own or any other layer.
+/// References to unknown names are left in place.
+pub fn resolve_references(vars: &Vars) -> Result<Vars, VarsError> {
+    let mut resolved = Vars::new();
+    for name in vars.keys() {
+        resolve_variable(name, vars, &mut resolved, &mut Vec::new())?;
+    }
+    Ok(resolved)
+}
+
+fn resolve_variable(
+    name: &str,
+    raw: &Vars,
+    resolved: &mut Vars,
+    stack: &mut Vec<String>,
+) -> Result<(), VarsError> {
+    if resolved.contains_key(name) {
+        return Ok(());
+    }
+    if let Some(start) = stack.iter().position(|entry| entry == name) {
+        let mut cycle = stack[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(VarsError::Cycle(cycle));
+    }
+
+    stack.push(name.to_string());
+    let value = &raw[name];
+    for reference in placeholder_names(value) {
+        if raw.contains_key(reference) {
+            resolve_variable(reference, raw, resolved, stack)?;
+        }
+    }
+    stack.pop();
+
+    let value = interpolate(value, resolved);
+    resolved.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// Parse a `KEY=VALUE` command line assignment.
+pub fn parse_assignment(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{raw}'"))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("variable name missing in '{raw}'"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+pub fn interpolate(template: &str, vars: &Vars) -> String {
+    replace_placeholders(template, |key| vars.get(key).map(String::as_str))
+}
+
+/// Substitute `{{name}}` placeholders in every string field of a step.
+pub fn interpolate_step(step: &Step, vars: &Vars) -> Step {
+    let path = |p: &std::path::Path| interpolate(&p.to_string_lossy(), vars).into();
+    match step {
+        Step::Run { run } => Step::Run {
+            run: interpolate(run, vars),
+        },
+        Step::Download { download } => {
+            let mut download = download.clone();
+            download.url = interpolate(&download.url, vars);
+            download.urls = download
+                .urls
+                .iter()
+                .map(|url| interpolate(url, vars))
+                .collect();
+            download.dest = path(&download.dest);
+            Step::Download { download }
+        }
+        Step::Extract { extract } => {
+            let mut extract = extract.clone();
+            extract.archive = path(&extract.archive);
+            extract.dest = path(&extract.dest);
+            Step::Extract { extract }
+        }
+        Step::TemplateConfig { template_config } => {
+            let mut template_config = template_config.clone();
+            template_config.source = path(&template_config.source);
+            template_config.dest = path(&template_config.dest);
+            // Plan-level variables are available to the rendered file, with the
+            // step's own vars taking precedence.
+            let mut merged: std::collections::HashMap<String, String> =
+                vars.clone().into_iter().collect();
+            for (key, value) in &template_config.vars {
+                merged.insert(key.clone(), interpolate(value, vars));
+            }
+            template_config.vars = merged;
+            Step::TemplateConfig { template_config }
+        }
+    }
+}
+
+/// The trimmed names of the `{{key}}` placeholders in `template`.
+fn placeholder_names(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut remainder = template;
+    while let Some(start) = remainder.find("{{") {
+        let Some(end) = remainder[start + 2..].find("}}") else {
+            break;
+        };
+        let end_index = start + 2 + end;
+        names.push(remainder[start + 2..end_index].trim());
+        remainder = &remainder[end_index + 2..];
+    }
+    names
+}
+
+/// Replace `{{key}}` placeholders using `lookup`. Unknown keys are left in
+/// place so commands containing literal braces (e.g. Go templates) survive.
+pub fn replace_placeholders<'v>(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<&'v str>,
+) -> String {
+    let mut result = String::new();
+    let mut remainder = template;
+    while let Some(start) = remainder.find("{{") {
+        if let Some(end) = remainder[start + 2..].find("}}") {
+            let end_index = start + 2 + end;
+            result.push_str(&remainder[..start]);
+            let key = &remainder[start + 2..end_index];
+            if let Some(value) = lookup(key.trim()) {
+                result.push_str(value);
+            } else {
+                result.push_str("{{");
+                result.push_str(key);
+                result.push_str("}}");
+            }
+            remainder = &remainder[end_index + 2..];
+            continue;
+        }
+        break;
+    }
+    result.push_str(remainder);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Vars, VarsError, apply_layer, interpolate, parse_assignment, resolve_references};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn later_layers_override_and_reference_earlier_ones() {
+        let mut vars = Vars::new();
+        vars.insert("app_version".into(), "1.2.0".into());
+
+        let mut manifest_vars = BTreeMap::new();
+        manifest_vars.insert("cdn".into(), "https://cdn.example.com".into());
+        manifest_vars.insert("channel".into(), "stable".into());
+        apply_layer(&mut vars, &manifest_vars);
+
+        let mut mode_vars = BTreeMap::new();
+        mode_vars.insert("bundle".into(), "{{cdn}}/{{app_version}}/app.zip".into());
+        mode_vars.insert("channel".into(), "beta".into());
+        apply_layer(&mut vars, &mode_vars);
+        let vars = resolve_references(&vars).unwrap();
+
+        assert_eq!(vars["bundle"], "https://cdn.example.com/1.2.0/app.zip");
+        assert_eq!(vars["channel"], "beta");
+    }
+
+    #[test]
+    fn resolves_references_within_a_layer() {
+        let mut vars = Vars::new();
+        let mut manifest_vars = BTreeMap::new();
+        manifest_vars.insert("url".into(), "{{base}}/x".into());
+        manifest_vars.insert("base".into(), "{{ host }}/dist".into());
+        manifest_vars.insert("host".into(), "https://cdn".into());
+        apply_layer(&mut vars, &manifest_vars);
+
+        let vars = resolve_references(&vars).unwrap();
+        assert_eq!(vars["url"], "https://cdn/dist/x");
+        assert_eq!(vars["base"], "https://cdn/dist");
+    }
+
+    #[test]
+    fn overrides_reach_variables_derived_from_them() {
+        let mut vars = Vars::new();
+        vars.insert("app_version".into(), "1.2.0".into());
+        let mut manifest_vars = BTreeMap::new();
+        manifest_vars.insert("cdn".into(), "https://cdn.example.com".into());
+        manifest_vars.insert("bundle".into(), "{{cdn}}/{{app_version}}/app.zip".into());
+        apply_layer(&mut vars, &manifest_vars);
+
+        let mut cli_vars = BTreeMap::new();
+        cli_vars.insert("app_version".into(), "1.3.0-rc1".into());
+        cli_vars.insert("cdn".into(), "https://mirror.local".into());
+        apply_layer(&mut vars, &cli_vars);
+
+        let vars = resolve_references(&vars).unwrap();
+        assert_eq!(vars["bundle"], "https://mirror.local/1.3.0-rc1/app.zip");
+    }
+
+    #[test]
+    fn reports_reference_cycles() {
+        let vars: Vars = [
+            ("a".to_string(), "{{b}}".to_string()),
+            ("b".to_string(), "x{{c}}".to_string()),
+            ("c".to_string(), "{{a}}".to_string()),
+        ]
+        .into();
+        let err = resolve_references(&vars).unwrap_err();
+        assert_eq!(
+            err,
+            VarsError::Cycle(vec!["a".into(), "b".into(), "c".into(), "a".into()])
+        );
+        assert_eq!(
+            err.to_string(),
+            "variable reference cycle: a -> b -> c -> a"
+        );
+
+        let vars: Vars = [("path".to_string(), "{{path}}/bin".to_string())].into();
+        assert!(resolve_references(&vars).is_err());
+    }
+
+    #[test]
+    fn leaves_unknown_placeholders_untouched() {
+        let vars = Vars::new();
+        assert_eq!(
+            interpolate("docker inspect --format '{{.Id}}'", &vars),
+            "docker inspect --format '{{.Id}}'"
+        );
+    }
+
+    #[test]
+    fn parses_assignments() {
+        assert_eq!(
+            parse_assignment("channel=beta=1").unwrap(),
+            ("channel".to_string(), "beta=1".to_string())
+        );
+        assert!(parse_assignment("novalue").is_err());
+        assert!(parse_assignment("=x").is_err());
+    }
+}